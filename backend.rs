@@ -0,0 +1,155 @@
+use lanes::{load_lanes, store_lanes, xor_bytes, extract_bytes};
+
+// The permutation used by the sponge, fixed at build time. 64-bit targets
+// use `opt64` and 32-bit ones the bit-interleaved `inter32` code. On
+// x86_64, `--cfg simd` switches to the `simd` code instead: the AVX2 path
+// in builds for AVX2 CPUs (see `simd` for the flags), and otherwise SSE2,
+// which every x86_64 CPU has. The SIMD paths have not been measured
+// against `opt64` yet, so they stay opt-in. Building with `--cfg portable`
+// always uses `opt64`.
+//
+// There is no runtime CPU detection. This compiler can only enable AVX2
+// for a whole crate, and LLVM may then use it in any function, so a
+// binary built with it can't fall back on older CPUs. SSE2 needs no check.
+
+#[deriving(Eq,ToStr,Clone)]
+pub enum Backend {
+    Portable,
+    Interleaved32,
+    Sse2,
+    Avx2,
+}
+
+#[cfg(target_arch = "x86_64", simd, target_feature = "avx2", not(portable))]
+mod imp {
+    use simd;
+    use super::{Backend, Avx2};
+
+    pub fn backend() -> Backend {
+        Avx2
+    }
+
+    pub fn permute_on_words(state: &mut [u64]) {
+        simd::permute_on_words_avx2(state)
+    }
+}
+
+#[cfg(target_arch = "x86_64", simd, not(target_feature = "avx2"), not(portable))]
+mod imp {
+    use simd;
+    use super::{Backend, Sse2};
+
+    pub fn backend() -> Backend {
+        Sse2
+    }
+
+    pub fn permute_on_words(state: &mut [u64]) {
+        simd::permute_on_words_sse2(state)
+    }
+}
+
+#[cfg(target_word_size = "32", not(portable))]
+mod imp {
+    use super::{Backend, Interleaved32};
+
+    pub fn backend() -> Backend {
+        Interleaved32
+    }
+
+    pub fn permute_on_words(state: &mut [u64]) {
+        ::inter32::permute_on_words(state)
+    }
+}
+
+#[cfg(target_word_size = "64", not(target_arch = "x86_64"))]
+#[cfg(target_word_size = "64", not(simd))]
+#[cfg(portable)]
+mod imp {
+    use super::{Backend, Portable};
+
+    pub fn backend() -> Backend {
+        Portable
+    }
+
+    pub fn permute_on_words(state: &mut [u64]) {
+        ::opt64::permute_on_words(state)
+    }
+}
+
+/// The code path `permute_on_words` runs in this build.
+pub fn backend() -> Backend {
+    imp::backend()
+}
+
+pub fn permute_on_words(state: &mut [u64]) {
+    imp::permute_on_words(state)
+}
+
+pub fn absorb_576_bits(state: &mut [u64], data: &[u8]) {
+    absorb(state, data, 9);
+}
+
+pub fn absorb_832_bits(state: &mut [u64], data: &[u8]) {
+    absorb(state, data, 13);
+}
+
+pub fn absorb_1024_bits(state: &mut [u64], data: &[u8]) {
+    absorb(state, data, 16);
+}
+
+pub fn absorb_1088_bits(state: &mut [u64], data: &[u8]) {
+    absorb(state, data, 17);
+}
+
+pub fn absorb_1152_bits(state: &mut [u64], data: &[u8]) {
+    absorb(state, data, 18);
+}
+
+pub fn absorb_1344_bits(state: &mut [u64], data: &[u8]) {
+    absorb(state, data, 21);
+}
+
+/// XOR the first `lane_count` lanes of `data` into `state` and permute.
+pub fn absorb(state: &mut [u64], data: &[u8], lane_count: uint) {
+    xor_bytes(state, data.slice_to(8 * lane_count), 0);
+    permute_on_words(state);
+}
+
+pub fn extract_1024_bits(state: &[u64], data: &mut [u8]) {
+    extract(state, data, 16);
+}
+
+pub fn extract(state: &[u64], data: &mut [u8], lane_count: uint) {
+    extract_bytes(state, data.mut_slice_to(8 * lane_count), 0);
+}
+
+/// Permute a state given in its 200-byte form.
+pub fn permute(state: &mut [u8]) {
+    let mut lanes = [0u64, ..25];
+    load_lanes(state, lanes);
+    permute_on_words(lanes);
+    store_lanes(lanes, state);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use opt64;
+
+    #[test]
+    fn test_against_portable() {
+        use std::rand::{XorShiftRng, RngUtil};
+
+        // Seeded, so a failing case comes back on the next run.
+        let mut rng = XorShiftRng::new_seeded(123456789, 362436069, 521288629, 88675123);
+        let mut state = rng.gen_bytes(200);
+        let mut portable = state.clone();
+
+        for _ in range(0, 10) {
+            permute(state);
+            opt64::permute(portable);
+        }
+
+        assert_eq!(state, portable);
+    }
+}
@@ -0,0 +1,227 @@
+use std::str;
+use std::u8;
+use std::vec;
+
+/// A message of any number of bits.
+///
+/// Bits are kept in the order the sponge absorbs them: bit i is bit i % 8
+/// of byte i / 8, and the unused high bits of the last byte are zero. Test
+/// vectors write a trailing partial byte in one of two ways, and each has
+/// its own constructors and conversions:
+///
+/// * Keccak order (`*_keccak_*`), used by FIPS 202 and the Keccak code
+///   package: the partial byte holds its bits in the least significant end.
+/// * NIST order (`*_nist_*`), used by the SHA-3 competition KAT files and
+///   `ShortMsgKAT_*.txt`: the partial byte holds its bits in the most
+///   significant end.
+///
+/// Whole bytes are the same in both.
+#[deriving(Eq, Clone)]
+pub struct BitString {
+    priv bytes: ~[u8],
+    priv len: uint,
+}
+
+fn decode_hex(hex: &str) -> Option<~[u8]> {
+    let chars = hex.iter().collect::<~[char]>();
+    if chars.len() % 2 != 0 {
+        return None;
+    }
+
+    let mut bytes = vec::with_capacity(chars.len() / 2);
+    for cs in chars.chunk_iter(2) {
+        match u8::from_str_radix(str::from_chars(cs), 16) {
+            Some(b) => bytes.push(b),
+            None => return None
+        }
+    }
+    Some(bytes)
+}
+
+fn encode_hex(bytes: &[u8]) -> ~str {
+    let mut s = ~"";
+    for &b in bytes.iter() {
+        s.push_str(fmt!("%02X", b as uint));
+    }
+    s
+}
+
+impl BitString {
+    pub fn new() -> BitString {
+        BitString { bytes: ~[], len: 0 }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> BitString {
+        BitString { bytes: bytes.to_owned(), len: 8 * bytes.len() }
+    }
+
+    /// The first `len` bits of `data` in Keccak order.
+    pub fn from_keccak_bits(data: &[u8], len: uint) -> BitString {
+        assert!(len <= 8 * data.len());
+
+        let mut bytes = data.slice_to((len + 7) / 8).to_owned();
+        if len % 8 != 0 {
+            bytes[len / 8] &= (1 << (len % 8)) - 1;
+        }
+        BitString { bytes: bytes, len: len }
+    }
+
+    /// The first `len` bits of `data` in NIST order.
+    pub fn from_nist_bits(data: &[u8], len: uint) -> BitString {
+        assert!(len <= 8 * data.len());
+
+        let mut bytes = data.slice_to((len + 7) / 8).to_owned();
+        if len % 8 != 0 {
+            bytes[len / 8] >>= 8 - len % 8;
+        }
+        BitString { bytes: bytes, len: len }
+    }
+
+    /// `len` bits given as hex in Keccak order. Extra trailing bytes, like
+    /// the `00` written for empty messages, are ignored.
+    pub fn from_keccak_hex(hex: &str, len: uint) -> Option<BitString> {
+        match decode_hex(hex) {
+            Some(data) => {
+                if len <= 8 * data.len() {
+                    Some(BitString::from_keccak_bits(data, len))
+                } else {
+                    None
+                }
+            }
+            None => None
+        }
+    }
+
+    /// `len` bits given as hex in NIST order, as in the KAT files.
+    pub fn from_nist_hex(hex: &str, len: uint) -> Option<BitString> {
+        match decode_hex(hex) {
+            Some(data) => {
+                if len <= 8 * data.len() {
+                    Some(BitString::from_nist_bits(data, len))
+                } else {
+                    None
+                }
+            }
+            None => None
+        }
+    }
+
+    pub fn len(&self) -> uint {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn bit(&self, i: uint) -> bool {
+        assert!(i < self.len);
+
+        self.bytes[i / 8] & (1 << (i % 8)) != 0
+    }
+
+    pub fn push(&mut self, bit: bool) {
+        if self.len % 8 == 0 {
+            self.bytes.push(0);
+        }
+        if bit {
+            self.bytes[self.len / 8] |= 1 << (self.len % 8);
+        }
+        self.len += 1;
+    }
+
+    /// Append the bits of `other`.
+    pub fn append(&mut self, other: &BitString) {
+        if self.len % 8 == 0 {
+            self.bytes.push_all(other.bytes);
+            self.len += other.len;
+            return;
+        }
+
+        for i in range(0, other.len) {
+            self.push(other.bit(i));
+        }
+    }
+
+    /// The bits in Keccak order, ready for `Keccak::input_bits`.
+    pub fn as_keccak_bytes<'a>(&'a self) -> &'a [u8] {
+        self.bytes.as_slice()
+    }
+
+    pub fn to_nist_bytes(&self) -> ~[u8] {
+        let mut bytes = self.bytes.clone();
+        if self.len % 8 != 0 {
+            bytes[self.len / 8] <<= 8 - self.len % 8;
+        }
+        bytes
+    }
+
+    pub fn to_keccak_hex(&self) -> ~str {
+        encode_hex(self.bytes)
+    }
+
+    pub fn to_nist_hex(&self) -> ~str {
+        encode_hex(self.to_nist_bytes())
+    }
+}
+
+impl Add<BitString, BitString> for BitString {
+    fn add(&self, other: &BitString) -> BitString {
+        let mut res = self.clone();
+        res.append(other);
+        res
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_conventions() {
+        // "Len = 3, Msg = C0" from ShortMsgKAT_*.txt.
+        let bs = BitString::from_nist_hex("C0", 3).unwrap();
+        assert_eq!(bs.len(), 3);
+        assert_eq!(bs.as_keccak_bytes(), [0x06u8].as_slice());
+        assert!(bs.bit(1) && bs.bit(2) && !bs.bit(0));
+        assert_eq!(bs.to_nist_hex(), ~"C0");
+        assert_eq!(bs.to_keccak_hex(), ~"06");
+        assert_eq!(BitString::from_keccak_hex("06", 3), Some(bs));
+
+        // Unused bits are dropped.
+        let a = BitString::from_keccak_hex("FF", 5).unwrap();
+        let b = BitString::from_nist_hex("FF", 5).unwrap();
+        assert_eq!(a, b);
+        assert_eq!(a.to_keccak_hex(), ~"1F");
+        assert_eq!(a.to_nist_hex(), ~"F8");
+
+        assert!(BitString::from_nist_hex("00", 0).unwrap().is_empty());
+        assert_eq!(BitString::from_nist_hex("0", 4), None);
+        assert_eq!(BitString::from_nist_hex("00", 9), None);
+        assert_eq!(BitString::from_nist_hex("G0", 4), None);
+    }
+
+    #[test]
+    fn test_concatenation() {
+        use std::rand::{weak_rng, RngUtil};
+
+        let mut rng = weak_rng();
+
+        for _ in range(0, 50) {
+            let a_len = rng.gen::<uint>() % 40;
+            let b_len = rng.gen::<uint>() % 40;
+            let a = BitString::from_keccak_bits(rng.gen_bytes(5), a_len);
+            let b = BitString::from_keccak_bits(rng.gen_bytes(5), b_len);
+
+            let mut bits = BitString::new();
+            for i in range(0, a_len) {
+                bits.push(a.bit(i));
+            }
+            for i in range(0, b_len) {
+                bits.push(b.bit(i));
+            }
+
+            assert_eq!(a + b, bits);
+        }
+    }
+}
@@ -5,7 +5,7 @@ pub static MAX_RATE_IN_BYTES: uint = 1536/8;
 
 
 
-pub static ROUND_CONST:  [uint, ..24] = [
+pub static ROUND_CONST: [u64, ..24] = [
     0x0000000000000001, 0x0000000000008082,
     0x800000000000808A, 0x8000000080008000,
     0x000000000000808B, 0x0000000080000001,
@@ -28,4 +28,13 @@ pub static RHO_OFFSETS: [uint, ..25] = [
   18,  2, 61, 56, 14,
 ];
 
+// Destination of lane x + 5 * y under pi: (x, y) -> (y, 2x + 3y).
+pub static PI_DEST: [uint, ..25] = [
+   0, 10, 20,  5, 15,
+  16,  1, 11, 21,  6,
+   7, 17,  2, 12, 22,
+  23,  8, 18,  3, 13,
+  14, 24,  9, 19,  4,
+];
+
 pub static ROUND_N: uint = 24;
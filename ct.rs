@@ -0,0 +1,59 @@
+// Comparisons of digests and tags whose running time doesn't depend on
+// where the inputs differ, only on their lengths, which are public.
+
+/// Whether `a` and `b` are equal, in constant time for equal lengths.
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let mut diff = 0u8;
+    for (x, y) in a.iter().zip(b.iter()) {
+        diff |= *x ^ *y;
+    }
+    diff == 0
+}
+
+/// Whether a tag of `len` bytes may be checked at all: at least `min_len`
+/// bytes, and never empty, which would match anything.
+pub fn tag_len_ok(len: uint, min_len: uint) -> bool {
+    len >= min_len && len != 0
+}
+
+/// Whether `tag` is `computed` truncated to its first `tag.len()` bytes,
+/// keeping at least `min_len` of them. A tag that fails `tag_len_ok` is
+/// rejected without comparing, so a forger can't shrink it to a guessable
+/// size.
+pub fn verify_tag(computed: &[u8], tag: &[u8], min_len: uint) -> bool {
+    if !tag_len_ok(tag.len(), min_len) || tag.len() > computed.len() {
+        return false;
+    }
+
+    ct_eq(computed.slice_to(tag.len()), tag)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ct_eq() {
+        assert!(ct_eq([], []));
+        assert!(ct_eq([1u8, 2, 3], [1u8, 2, 3]));
+        assert!(!ct_eq([1u8, 2, 3], [1u8, 2, 4]));
+        assert!(!ct_eq([1u8, 2, 3], [1u8, 2]));
+    }
+
+    #[test]
+    fn test_verify_tag() {
+        let computed = [9u8, 8, 7, 6, 5, 4, 3, 2];
+
+        assert!(verify_tag(computed, computed, 8));
+        assert!(verify_tag(computed, [9u8, 8, 7, 6], 4));
+        assert!(!verify_tag(computed, [9u8, 8, 7], 4));
+        assert!(!verify_tag(computed, [9u8, 8, 7, 0], 4));
+        assert!(!verify_tag(computed, [9u8, 8, 7, 6, 5, 4, 3, 2, 1], 4));
+        assert!(verify_tag(computed, [9u8], 0));
+        assert!(!verify_tag(computed, [], 0));
+    }
+}
@@ -0,0 +1,156 @@
+// The hashing traits of the RustCrypto `digest` crate, with the same names
+// and methods, so code written generically against them also runs on this
+// crate's hashers. This compiler has neither associated types nor
+// constants, so two things differ: sizes are methods returning bytes, and
+// `ExtendableOutput` takes its reader type as a parameter.
+//
+// `nist::Keccak`, `fips202::Shake`, `hasher::Hasher` and `hasher::Reader`
+// implement them. Failures that the crate's own methods return as a
+// `SpongeError`, like input after output, fail the task here, since these
+// signatures have no way to return them.
+
+use std::vec;
+
+/// Hashers with a fixed output length.
+pub trait OutputSizeUser {
+    /// The digest length in bytes.
+    fn output_size(&self) -> uint;
+}
+
+/// Hashers that process input in blocks, like HMAC needs to know.
+pub trait BlockSizeUser {
+    /// The block length in bytes, the rate of a sponge.
+    fn block_size(&self) -> uint;
+}
+
+/// Marks cryptographic hash functions, as opposed to checksums.
+pub trait HashMarker {}
+
+pub trait Update {
+    /// Absorb `data`. Can be called any number of times.
+    fn update(&mut self, data: &[u8]);
+}
+
+pub trait Reset {
+    /// Drop the input so far and start over.
+    fn reset(&mut self);
+}
+
+pub trait FixedOutput: Update + OutputSizeUser {
+    /// Write the digest to `out`, which must be `output_size()` bytes.
+    fn finalize_into(self, out: &mut [u8]);
+
+    /// The digest in a new vector.
+    fn finalize_fixed(self) -> ~[u8] {
+        let mut out = vec::from_elem(self.output_size(), 0u8);
+        self.finalize_into(out);
+        out
+    }
+}
+
+pub trait FixedOutputReset: FixedOutput + Reset {
+    /// Like `finalize_into`, but leaves the hasher reset for the next
+    /// message.
+    fn finalize_into_reset(&mut self, out: &mut [u8]);
+
+    fn finalize_fixed_reset(&mut self) -> ~[u8] {
+        let mut out = vec::from_elem(self.output_size(), 0u8);
+        self.finalize_into_reset(out);
+        out
+    }
+}
+
+/// The output stream of an extendable-output function.
+pub trait XofReader {
+    /// Fill `out` with the next `out.len()` bytes of output.
+    fn read(&mut self, out: &mut [u8]);
+}
+
+/// Hashers with output of any length, read through `R`.
+pub trait ExtendableOutput<R: XofReader>: Update {
+    /// Pad the message and start reading output.
+    fn finalize_xof(self) -> R;
+
+    /// Fill `out` with the first `out.len()` bytes of output.
+    fn finalize_xof_into(self, out: &mut [u8]) {
+        let mut reader = self.finalize_xof();
+        reader.read(out);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nist::Keccak;
+    use fips202::{Shake, shake128};
+    use std::vec;
+
+    // HMAC as the `hmac` crate computes it, from a fresh copy of `proto`
+    // per pass.
+    fn hmac<D: FixedOutput + BlockSizeUser + Clone>(proto: &D, key: &[u8], msg: &[u8]) -> ~[u8] {
+        let mut k = vec::from_elem(proto.block_size(), 0u8);
+        assert!(key.len() <= k.len());
+        for i in range(0, key.len()) {
+            k[i] = key[i];
+        }
+
+        let mut inner = proto.clone();
+        inner.update(k.iter().map(|&b| b ^ 0x36).collect::<~[u8]>());
+        inner.update(msg);
+        let inner = inner.finalize_fixed();
+
+        let mut outer = proto.clone();
+        outer.update(k.iter().map(|&b| b ^ 0x5c).collect::<~[u8]>());
+        outer.update(inner);
+        outer.finalize_fixed()
+    }
+
+    fn xof<R: XofReader, H: ExtendableOutput<R>>(hasher: H, msg: &[u8], out: &mut [u8]) {
+        let mut hasher = hasher;
+        hasher.update(msg);
+        hasher.finalize_xof_into(out);
+    }
+
+    #[test]
+    fn test_fixed() {
+        let kc = Keccak::new(256);
+        let hasher = Keccak::hasher(256);
+        assert_eq!((kc.output_size(), kc.block_size()), (32u, 136u));
+        assert_eq!((hasher.output_size(), hasher.block_size()), (32u, 136u));
+
+        let mac = hmac(&kc, "key".as_bytes(), "msg".as_bytes());
+        assert_eq!(mac.len(), 32);
+        assert_eq!(mac, hmac(&hasher, "key".as_bytes(), "msg".as_bytes()));
+        assert!(mac != hmac(&kc, "kez".as_bytes(), "msg".as_bytes()));
+
+        let mut kc = Keccak::new(224);
+        kc.update([1u8, 2, 3]);
+        let first = kc.finalize_fixed_reset();
+        kc.update([1u8, 2, 3]);
+        assert_eq!(kc.finalize_fixed_reset(), first);
+        assert_eq!(first.len(), 28);
+    }
+
+    #[test]
+    fn test_xof() {
+        let mut expected = [0u8, ..200];
+        shake128([0x61u8], expected);
+
+        let mut out = [0u8, ..200];
+        xof(Shake::new(128), [0x61u8], out);
+        assert_eq!(out.as_slice(), expected.as_slice());
+
+        let mut out = [0u8, ..200];
+        xof(Shake::hasher(128), [0x61u8], out);
+        assert_eq!(out.as_slice(), expected.as_slice());
+
+        // A reader continues where the last read ended.
+        let mut sh = Shake::new(128);
+        sh.update([0x61u8]);
+        let mut reader = sh.finalize_xof();
+        let mut out = [0u8, ..200];
+        reader.read(out.mut_slice_to(77));
+        reader.read(out.mut_slice_from(77));
+        assert_eq!(out.as_slice(), expected.as_slice());
+    }
+}
@@ -0,0 +1,257 @@
+use sponge::{SpongeError, InvalidParameters, AbsorbAfterSqueeze, BufferTooSmall};
+use permutation::Permutation;
+use zeroize::Zeroize;
+use std::cmp::min;
+
+/// The sponge without an input queue: input is XORed straight into the
+/// permutation state, the way the XKCP does it, so besides the state there
+/// are only a few counters. Gives the same output as `Sponge`, for targets
+/// where its `data_queue` copy of the block doesn't fit. Nothing here
+/// allocates or logs.
+pub struct DirectSponge<P> {
+    priv state: P,
+    priv rate: uint,
+    priv capacity: uint,
+    // Bits of the current block absorbed so far, or squeezed so far once
+    // `squeezing`. The permutation runs when the next bit needs a new
+    // block.
+    priv pos: uint,
+    priv squeezing: bool,
+}
+
+impl<P: Permutation> DirectSponge<P> {
+    pub fn new(perm: P, rate: uint, capacity: uint) -> Result<DirectSponge<P>, SpongeError> {
+        if rate + capacity != perm.width() || rate % 8 != 0 || rate == 0 {
+            return Err(InvalidParameters);
+        }
+
+        let mut perm = perm;
+        perm.initialize();
+
+        Ok(DirectSponge {
+            state: perm,
+            rate: rate,
+            capacity: capacity,
+            pos: 0,
+            squeezing: false,
+        })
+    }
+
+    pub fn rate(&self) -> uint {
+        self.rate
+    }
+
+    pub fn capacity(&self) -> uint {
+        self.capacity
+    }
+
+    pub fn reset(&mut self) {
+        self.state.initialize();
+        self.pos = 0;
+        self.squeezing = false;
+    }
+
+    /// Absorb the first `bit_len` bits of `data`, as `Sponge::absorb`.
+    pub fn absorb(&mut self, data: &[u8], bit_len: uint) -> Result<(), SpongeError> {
+        if self.squeezing {
+            return Err(AbsorbAfterSqueeze);
+        }
+        if bit_len > data.len() * 8 {
+            return Err(BufferTooSmall);
+        }
+
+        let mut i = 0;
+        while i < bit_len {
+            if self.pos == self.rate {
+                self.state.permute();
+                self.pos = 0;
+            }
+
+            let room = self.rate - self.pos;
+            if self.pos % 8 == 0 && i % 8 == 0 && bit_len - i >= 8 {
+                let n = min((bit_len - i) / 8, room / 8);
+                self.state.add_bytes(data.slice(i / 8, i / 8 + n), self.pos / 8);
+                self.pos += 8 * n;
+                i += 8 * n;
+            } else {
+                // At most a byte's worth, within one input and one state
+                // byte.
+                let k = min(min(8 - i % 8, 8 - self.pos % 8), min(bit_len - i, room));
+                let bits = (data[i / 8] >> (i % 8)) & ((1u << k) - 1) as u8;
+                self.state.add_bytes([bits << (self.pos % 8)], self.pos / 8);
+                self.pos += k;
+                i += k;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write the next `bit_len` bits of output to `out`, as
+    /// `Sponge::squeeze`.
+    pub fn squeeze(&mut self, out: &mut [u8], bit_len: uint) -> Result<(), SpongeError> {
+        if bit_len > out.len() * 8 {
+            return Err(BufferTooSmall);
+        }
+
+        if !self.squeezing {
+            self.pad();
+        }
+
+        let mut i = 0;
+        while i < bit_len {
+            if self.pos == self.rate {
+                self.state.permute();
+                self.pos = 0;
+            }
+
+            let room = self.rate - self.pos;
+            if self.pos % 8 == 0 && i % 8 == 0 && bit_len - i >= 8 {
+                let n = min((bit_len - i) / 8, room / 8);
+                self.state.extract_bytes(out.mut_slice(i / 8, i / 8 + n), self.pos / 8);
+                self.pos += 8 * n;
+                i += 8 * n;
+            } else {
+                let k = min(min(8 - i % 8, 8 - self.pos % 8), min(bit_len - i, room));
+                let mut byte = [0u8];
+                self.state.extract_bytes(byte, self.pos / 8);
+                let bits = (byte[0] >> (self.pos % 8)) & ((1u << k) - 1) as u8;
+                if i % 8 == 0 {
+                    out[i / 8] = bits;
+                } else {
+                    out[i / 8] |= bits << (i % 8);
+                }
+                self.pos += k;
+                i += k;
+            }
+        }
+
+        Ok(())
+    }
+
+    // pad10*1. When only the last bit of the block is free, the two ones go
+    // in separate blocks.
+    fn pad(&mut self) {
+        if self.pos == self.rate {
+            self.state.permute();
+            self.pos = 0;
+        }
+
+        self.xor_bit(self.pos);
+        if self.pos == self.rate - 1 {
+            self.state.permute();
+        }
+        self.xor_bit(self.rate - 1);
+        self.state.permute();
+
+        self.pos = 0;
+        self.squeezing = true;
+    }
+
+    fn xor_bit(&mut self, bit: uint) {
+        self.state.add_bytes([1u8 << (bit % 8)], bit / 8);
+    }
+}
+
+impl<P: Permutation + Clone> Clone for DirectSponge<P> {
+    fn clone(&self) -> DirectSponge<P> {
+        DirectSponge {
+            state: self.state.clone(),
+            rate: self.rate,
+            capacity: self.capacity,
+            pos: self.pos,
+            squeezing: self.squeezing,
+        }
+    }
+}
+
+impl<P: Permutation> Zeroize for DirectSponge<P> {
+    fn zeroize(&mut self) {
+        self.state.zeroize();
+        self.pos = 0;
+        self.squeezing = false;
+    }
+}
+
+#[cfg(zeroize)]
+#[unsafe_destructor]
+impl<P: Permutation> Drop for DirectSponge<P> {
+    fn drop(&self) {
+        use std::cast;
+
+        unsafe {
+            cast::transmute_mut(self).zeroize();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sponge::{Sponge, AbsorbAfterSqueeze};
+    use permutation::{Permutation, KeccakF1600, KeccakP200};
+    use std::rand::{XorShiftRng, RngUtil};
+
+    fn check_against_sponge<P: Permutation + Clone>(perm: P, rate: uint) {
+        let capacity = perm.width() - rate;
+        // Seeded, so a failing case comes back on the next run.
+        let mut rng = XorShiftRng::new_seeded(123456789, 362436069, 521288629, 88675123);
+
+        for _ in range(0, 50) {
+            let mut direct = DirectSponge::new(perm.clone(), rate, capacity).unwrap();
+            let mut sponge = Sponge::new(perm.clone(), rate, capacity).unwrap();
+
+            // Chunks of any bit length, each starting on a fresh byte of
+            // `data`, so the sponges are often mid-byte between calls.
+            let data = rng.gen_bytes(3 * rate / 8);
+            let mut i = 0;
+            while i < data.len() {
+                let mut n = rng.gen::<uint>() % (rate + 20);
+                if n > 8 * (data.len() - i) {
+                    n = 8 * (data.len() - i);
+                }
+                direct.absorb(data.slice_from(i), n).unwrap();
+                sponge.absorb(data.slice_from(i), n).unwrap();
+                i += (n + 7) / 8;
+                if rng.gen::<uint>() % 4 == 0 {
+                    break;
+                }
+            }
+
+            let mut a = [0u8, ..64];
+            let mut b = [0u8, ..64];
+            let mut done = 0;
+            while done < 512 {
+                let n = 1 + rng.gen::<uint>() % (512 - done);
+                direct.squeeze(a, n).unwrap();
+                sponge.squeeze(b, n).unwrap();
+                assert_eq!(a.slice_to((n + 7) / 8), b.slice_to((n + 7) / 8));
+                done += n;
+            }
+        }
+    }
+
+    #[test]
+    fn test_against_sponge() {
+        check_against_sponge(KeccakF1600::new(), 1088);
+        check_against_sponge(KeccakP200::new(18), 72);
+    }
+
+    #[test]
+    fn test_padding_across_blocks() {
+        // With rate - 1 bits absorbed, the padding takes two blocks.
+        let data = [0xA5u8, ..136];
+        let mut direct = DirectSponge::new(KeccakF1600::new(), 1088, 512).unwrap();
+        let mut sponge = Sponge::new(KeccakF1600::new(), 1088, 512).unwrap();
+        direct.absorb(data, 1087).unwrap();
+        sponge.absorb(data, 1087).unwrap();
+
+        let mut a = [0u8, ..32];
+        let mut b = [0u8, ..32];
+        direct.squeeze(a, 256).unwrap();
+        sponge.squeeze(b, 256).unwrap();
+        assert_eq!(a.as_slice(), b.as_slice());
+
+        assert_eq!(direct.absorb(data, 8), Err(AbsorbAfterSqueeze));
+    }
+}
@@ -0,0 +1,134 @@
+use sponge::{SpongeError, InvalidParameters, OutputLengthMismatch, InputTooLong};
+use permutation::Permutation;
+use zeroize::Zeroize;
+
+/// The duplex construction ("Duplexing the sponge", Bertoni et al.) on any
+/// permutation: every call pads and absorbs one input block, applies the
+/// permutation and returns up to `rate` bits of output.
+pub struct Duplex<P> {
+    priv state: P,
+    priv rate: uint,
+}
+
+impl<P: Permutation> Duplex<P> {
+    /// Fails with `InvalidParameters` unless `rate` is a whole number of
+    /// bytes, more than one, and below the permutation width.
+    pub fn new(perm: P, rate: uint) -> Result<Duplex<P>, SpongeError> {
+        if rate % 8 != 0 || rate <= 8 || rate >= perm.width() {
+            return Err(InvalidParameters);
+        }
+
+        let mut perm = perm;
+        perm.initialize();
+
+        Ok(Duplex {
+            state: perm,
+            rate: rate,
+        })
+    }
+
+    pub fn rate(&self) -> uint {
+        self.rate
+    }
+
+    /// The longest input a single `duplexing` call takes, in bytes; the
+    /// rest of the block is needed for the padding.
+    pub fn max_input_len(&self) -> uint {
+        self.rate / 8 - 1
+    }
+
+    /// Absorb `input` padded with pad10*1 to a full block, permute, and
+    /// fill `out` from the new state. Fails with `InputTooLong` for more
+    /// than `max_input_len()` bytes of input and with
+    /// `OutputLengthMismatch` for more than `rate / 8` bytes of output,
+    /// leaving the state as it was.
+    pub fn duplexing(&mut self, input: &[u8], out: &mut [u8]) -> Result<(), SpongeError> {
+        if input.len() > self.max_input_len() {
+            return Err(InputTooLong);
+        }
+        if out.len() > self.rate / 8 {
+            return Err(OutputLengthMismatch);
+        }
+
+        self.state.add_bytes(input, 0);
+        self.state.add_bytes([0x01u8], input.len());
+        self.state.add_bytes([0x80u8], self.rate / 8 - 1);
+        self.state.permute();
+        self.state.extract_bytes(out, 0);
+        Ok(())
+    }
+}
+
+impl<P: Permutation> Zeroize for Duplex<P> {
+    fn zeroize(&mut self) {
+        self.state.zeroize();
+    }
+}
+
+#[cfg(zeroize)]
+#[unsafe_destructor]
+impl<P: Permutation> Drop for Duplex<P> {
+    fn drop(&self) {
+        use std::cast;
+
+        unsafe {
+            cast::transmute_mut(self).zeroize();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use permutation::KeccakF1600;
+    use sponge::Sponge;
+    use std::vec;
+
+    #[test]
+    fn test_duplex_matches_sponge() {
+        // Each duplexing output equals the sponge output on all the padded
+        // blocks absorbed so far, the last one unpadded.
+        let first = vec::from_fn(50, |i| i as u8);
+        let second = vec::from_fn(135, |i| (3 * i) as u8);
+
+        let mut dp = Duplex::new(KeccakF1600::new(), 1088).unwrap();
+        let mut out1 = [0u8, ..136];
+        let mut out2 = [0u8, ..136];
+        dp.duplexing(first, out1).unwrap();
+        dp.duplexing(second, out2).unwrap();
+
+        let mut sp = Sponge::new(KeccakF1600::new(), 1088, 512).unwrap();
+        sp.absorb(first, first.len() * 8).unwrap();
+        let mut expected = [0u8, ..136];
+        sp.squeeze(expected, 1088).unwrap();
+        assert_eq!(out1.as_slice(), expected.as_slice());
+
+        let mut padded = vec::from_elem(136, 0u8);
+        for (d, s) in padded.mut_iter().zip(first.iter()) {
+            *d = *s;
+        }
+        padded[first.len()] |= 0x01;
+        padded[135] |= 0x80;
+
+        let mut sp = Sponge::new(KeccakF1600::new(), 1088, 512).unwrap();
+        sp.absorb(padded, 1088).unwrap();
+        sp.absorb(second, second.len() * 8).unwrap();
+        sp.squeeze(expected, 1088).unwrap();
+        assert_eq!(out2.as_slice(), expected.as_slice());
+    }
+
+    #[test]
+    fn test_errors() {
+        use sponge::{InvalidParameters, OutputLengthMismatch, InputTooLong};
+        use permutation::KeccakP200;
+
+        assert_eq!(Duplex::new(KeccakF1600::new(), 1084).unwrap_err(), InvalidParameters);
+        assert_eq!(Duplex::new(KeccakF1600::new(), 1600).unwrap_err(), InvalidParameters);
+        assert_eq!(Duplex::new(KeccakP200::new(18), 8).unwrap_err(), InvalidParameters);
+
+        let mut dp = Duplex::new(KeccakF1600::new(), 1088).unwrap();
+        assert_eq!(dp.duplexing([0u8, ..136], [0u8, ..8]), Err(InputTooLong));
+        assert_eq!(dp.duplexing([0u8, ..8], [0u8, ..137]), Err(OutputLengthMismatch));
+        assert!(dp.duplexing([0u8, ..135], [0u8, ..136]).is_ok());
+    }
+}
@@ -0,0 +1,332 @@
+use sponge::*;
+use hasher::{Hasher, KeccakHasher};
+use zeroize::Zeroize;
+use ct::{ct_eq, tag_len_ok};
+use midstate;
+use digest;
+use std::rt::io;
+use std::vec;
+use permutation::KeccakF1600;
+
+/// SHAKE128 and SHAKE256 extendable-output functions from FIPS 202.
+///
+/// These are the same sponge as `nist::Keccak`, with the `1111` domain
+/// separation suffix appended to the message before padding.
+pub struct Shake {
+    priv sponge_state: SpongeState,
+    priv security: uint,
+    priv finalized: bool,
+}
+
+impl Shake {
+    pub fn new(security: uint) -> Shake {
+        match Shake::try_new(security) {
+            Ok(sh) => sh,
+            Err(_) => fail!("security must be 128 or 256")
+        }
+    }
+
+    /// Like `new`, but returns `InvalidParameters` for other strengths.
+    pub fn try_new(security: uint) -> Result<Shake, SpongeError> {
+        let sponge = match security {
+            128 => Sponge::new(KeccakF1600::new(), 1344, 256),
+            256 => Sponge::new(KeccakF1600::new(), 1088, 512),
+            _ => Err(InvalidParameters)
+        };
+
+        match sponge {
+            Ok(sponge) => Ok(Shake {
+                sponge_state: sponge,
+                security: security,
+                finalized: false,
+            }),
+            Err(e) => Err(e)
+        }
+    }
+
+    /// SHAKE with the typestate API: read the output from the `Reader`
+    /// that `finalize_xof` returns.
+    pub fn hasher(security: uint) -> KeccakHasher {
+        let sh = Shake::new(security);
+        Hasher::new(sh.sponge_state, 0x0f, 4, 0)
+    }
+
+    /// Absorb `input`. Fails with `AbsorbAfterSqueeze` once output has been
+    /// read.
+    pub fn input(&mut self, input: &[u8]) -> Result<(), SpongeError> {
+        if self.finalized {
+            return Err(AbsorbAfterSqueeze);
+        }
+
+        self.sponge_state.absorb(input, input.len() * 8)
+    }
+
+    /// Fill `out` with the next `out.len()` bytes of output. Can be called
+    /// repeatedly to read a longer stream, but fails with
+    /// `UnalignedBitState` if a `squeeze_bits` call left it mid-byte.
+    pub fn squeeze(&mut self, out: &mut [u8]) -> Result<(), SpongeError> {
+        if self.sponge_state.is_output_unaligned() {
+            return Err(UnalignedBitState);
+        }
+
+        self.squeeze_bits(out, out.len() * 8)
+    }
+
+    /// Like `squeeze`, for the next `bit_len` bits. As in FIPS 202, a partial
+    /// last byte holds its bits in the least significant end and the rest
+    /// are cleared; the next call continues with the following bit.
+    pub fn squeeze_bits(&mut self, out: &mut [u8], bit_len: uint) -> Result<(), SpongeError> {
+        if bit_len > out.len() * 8 {
+            return Err(BufferTooSmall);
+        }
+
+        if !self.finalized {
+            match self.sponge_state.absorb(&[0x0fu8], 4) {
+                Ok(()) => self.finalized = true,
+                Err(e) => return Err(e)
+            }
+        }
+
+        self.sponge_state.squeeze(out, bit_len)
+    }
+
+    /// Whether the next `expected.len()` bytes of output are `expected`,
+    /// compared in constant time. An empty `expected` is rejected without
+    /// reading.
+    pub fn verify(&mut self, expected: &[u8]) -> bool {
+        if !tag_len_ok(expected.len(), 1) {
+            return false;
+        }
+
+        let mut out = vec::from_elem(expected.len(), 0u8);
+        if self.squeeze(out).is_err() {
+            return false;
+        }
+        ct_eq(out, expected)
+    }
+
+    /// The state so far, for `import_midstate` to continue from, possibly
+    /// in another process.
+    pub fn export_midstate(&self) -> ~[u8] {
+        midstate::export(&self.sponge_state, 0x0f, 4)
+    }
+
+    /// The instance `export_midstate` saved, or `InvalidMidstate` if `data`
+    /// isn't a valid record for SHAKE.
+    pub fn import_midstate(data: &[u8]) -> Result<Shake, SpongeError> {
+        match midstate::import(KeccakF1600::new(), data) {
+            Ok((sponge, 0x0f, 4)) => {
+                let security = match sponge.capacity {
+                    256 => 128,
+                    512 => 256,
+                    _ => return Err(InvalidMidstate)
+                };
+                if sponge.fixed_out_len != 0 {
+                    return Err(InvalidMidstate);
+                }
+                let finalized = sponge.squeezing;
+
+                Ok(Shake {
+                    sponge_state: sponge,
+                    security: security,
+                    finalized: finalized,
+                })
+            }
+            Ok(_) => Err(InvalidMidstate),
+            Err(e) => Err(e)
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.sponge_state.reset();
+        self.finalized = false;
+    }
+
+    pub fn security(&self) -> uint {
+        self.security
+    }
+}
+
+impl io::Writer for Shake {
+    fn write(&mut self, buf: &[u8]) {
+        match self.input(buf) {
+            Ok(()) => (),
+            Err(e) => fail!(e.to_str())
+        }
+    }
+
+    fn flush(&mut self) {}
+}
+
+// An endless stream; `hasher::Reader` can also seek in it.
+impl io::Reader for Shake {
+    fn read(&mut self, buf: &mut [u8]) -> Option<uint> {
+        match self.squeeze(buf) {
+            Ok(()) => Some(buf.len()),
+            Err(e) => fail!(e.to_str())
+        }
+    }
+
+    fn eof(&mut self) -> bool {
+        false
+    }
+}
+
+impl Clone for Shake {
+    fn clone(&self) -> Shake {
+        Shake {
+            sponge_state: self.sponge_state.clone(),
+            security: self.security,
+            finalized: self.finalized,
+        }
+    }
+}
+
+impl digest::Update for Shake {
+    fn update(&mut self, data: &[u8]) {
+        match self.input(data) {
+            Ok(()) => (),
+            Err(e) => fail!(e.to_str())
+        }
+    }
+}
+
+impl digest::Reset for Shake {
+    fn reset(&mut self) {
+        self.reset();
+    }
+}
+
+impl digest::BlockSizeUser for Shake {
+    fn block_size(&self) -> uint {
+        self.sponge_state.rate / 8
+    }
+}
+
+// `Shake` is its own reader. Padding happens here rather than on the first
+// read, so an `update` on the reader fails instead of changing the output.
+impl digest::ExtendableOutput<Shake> for Shake {
+    fn finalize_xof(self) -> Shake {
+        let mut sh = self;
+        match sh.squeeze_bits([], 0) {
+            Ok(()) => sh,
+            Err(e) => fail!(e.to_str())
+        }
+    }
+}
+
+impl digest::XofReader for Shake {
+    fn read(&mut self, out: &mut [u8]) {
+        match self.squeeze(out) {
+            Ok(()) => (),
+            Err(e) => fail!(e.to_str())
+        }
+    }
+}
+
+impl digest::HashMarker for Shake {}
+
+impl Zeroize for Shake {
+    fn zeroize(&mut self) {
+        self.sponge_state.zeroize();
+        self.finalized = false;
+    }
+}
+
+pub fn shake128(input: &[u8], out: &mut [u8]) {
+    let mut sh = Shake::new(128);
+    sh.input(input).unwrap();
+    sh.squeeze(out).unwrap();
+}
+
+pub fn shake256(input: &[u8], out: &mut [u8]) {
+    let mut sh = Shake::new(256);
+    sh.input(input).unwrap();
+    sh.squeeze(out).unwrap();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static SHAKE128_EMPTY: [u8, ..32] = [
+        0x7f, 0x9c, 0x2b, 0xa4, 0xe8, 0x8f, 0x82, 0x7d,
+        0x61, 0x60, 0x45, 0x50, 0x76, 0x05, 0x85, 0x3e,
+        0xd7, 0x3b, 0x80, 0x93, 0xf6, 0xef, 0xbc, 0x88,
+        0xeb, 0x1a, 0x6e, 0xac, 0xfa, 0x66, 0xef, 0x26,
+    ];
+
+    static SHAKE256_EMPTY: [u8, ..64] = [
+        0x46, 0xb9, 0xdd, 0x2b, 0x0b, 0xa8, 0x8d, 0x13,
+        0x23, 0x3b, 0x3f, 0xeb, 0x74, 0x3e, 0xeb, 0x24,
+        0x3f, 0xcd, 0x52, 0xea, 0x62, 0xb8, 0x1b, 0x82,
+        0xb5, 0x0c, 0x27, 0x64, 0x6e, 0xd5, 0x76, 0x2f,
+        0xd7, 0x5d, 0xc4, 0xdd, 0xd8, 0xc0, 0xf2, 0x00,
+        0xcb, 0x05, 0x01, 0x9d, 0x67, 0xb5, 0x92, 0xf6,
+        0xfc, 0x82, 0x1c, 0x49, 0x47, 0x9a, 0xb4, 0x86,
+        0x40, 0x29, 0x2e, 0xac, 0xb3, 0xb7, 0xc4, 0xbe,
+    ];
+
+    #[test]
+    fn test_shake_empty() {
+        let mut out = [0u8, ..32];
+        shake128([], out);
+        assert_eq!(out.as_slice(), SHAKE128_EMPTY.as_slice());
+
+        let mut out = [0u8, ..64];
+        shake256([], out);
+        assert_eq!(out.as_slice(), SHAKE256_EMPTY.as_slice());
+    }
+
+    #[test]
+    fn test_shake_incremental_squeeze() {
+        use std::vec;
+
+        let msg = vec::from_elem(1000, 0xa3u8);
+        let mut once = vec::from_elem(500, 0u8);
+        shake256(msg, once);
+
+        let mut sh = Shake::new(256);
+        sh.input(msg.slice_to(333)).unwrap();
+        sh.input(msg.slice_from(333)).unwrap();
+
+        let mut pieces = vec::from_elem(500, 0u8);
+        sh.squeeze(pieces.mut_slice(0, 136)).unwrap();
+        sh.squeeze(pieces.mut_slice(136, 200)).unwrap();
+        sh.squeeze(pieces.mut_slice(200, 500)).unwrap();
+
+        assert_eq!(once, pieces);
+    }
+
+    #[test]
+    fn test_shake_squeeze_bits() {
+        let mut sh = Shake::new(128);
+        let mut head = [0u8, ..2];
+        let mut tail = [0u8, ..31];
+        sh.squeeze_bits(head, 12).unwrap();
+        sh.squeeze_bits(tail, 244).unwrap();
+
+        assert_eq!(head[0], SHAKE128_EMPTY[0]);
+        assert_eq!(head[1], SHAKE128_EMPTY[1] & 0x0F);
+        for i in range(0u, 30) {
+            let expected = (SHAKE128_EMPTY[i + 1] >> 4) | (SHAKE128_EMPTY[i + 2] << 4);
+            assert_eq!(tail[i], expected);
+        }
+    }
+
+    #[test]
+    fn test_shake_errors() {
+        use sponge::{InvalidParameters, AbsorbAfterSqueeze, UnalignedBitState, BufferTooSmall};
+
+        assert_eq!(Shake::try_new(192).unwrap_err(), InvalidParameters);
+
+        let mut sh = Shake::new(128);
+        let mut out = [0u8, ..4];
+        assert_eq!(sh.squeeze_bits(out, 33), Err(BufferTooSmall));
+        assert!(sh.squeeze_bits(out, 3).is_ok());
+        assert_eq!(sh.input([1u8]), Err(AbsorbAfterSqueeze));
+        assert_eq!(sh.squeeze(out), Err(UnalignedBitState));
+        assert!(sh.squeeze_bits(out, 5).is_ok());
+        assert!(sh.squeeze(out).is_ok());
+    }
+}
@@ -0,0 +1,445 @@
+use sponge::*;
+use permutation::{Permutation, KeccakF1600};
+use zeroize::Zeroize;
+use ct::{ct_eq, verify_tag, tag_len_ok};
+use digest;
+use std::rt::io;
+use std::vec;
+use std::vec::MutableCloneableVector;
+
+/// The absorbing phase of a sponge hash. Finalizing consumes it and hands
+/// back a `Reader`, so input after output doesn't compile instead of
+/// failing with `AbsorbAfterSqueeze`. `nist::Keccak` and `fips202::Shake`
+/// keep the runtime-checked API on top of the same sponge.
+pub struct Hasher<P> {
+    priv sponge: Sponge<P>,
+    priv suffix: u8,
+    priv suffix_len: uint,
+    priv output_bits: uint,
+}
+
+/// The squeezing phase of a sponge hash. Besides its own methods it is an
+/// `io::Reader` of the output stream, and an `io::Seek` that goes back by
+/// starting over from a copy of the state taken right after padding.
+pub struct Reader<P> {
+    priv sponge: Sponge<P>,
+    priv start: Sponge<P>,
+    // Output bits read since `start`.
+    priv pos: u64,
+}
+
+pub type KeccakHasher = Hasher<KeccakF1600>;
+pub type KeccakReader = Reader<KeccakF1600>;
+
+impl<P: Permutation + Clone> Hasher<P> {
+    /// Hash with a fresh `sponge`, appending the `suffix_len` low bits of
+    /// `suffix` to the message before padding, like the `1111` of SHAKE.
+    /// `output_bits` is the digest length for `finalize`, or 0 for an
+    /// instance that is only read through `finalize_xof`.
+    pub fn new(sponge: Sponge<P>, suffix: u8, suffix_len: uint, output_bits: uint) -> Hasher<P> {
+        assert!(!sponge.squeezing && sponge.bits_in_queue == 0);
+        assert!(suffix_len < 8);
+
+        Hasher {
+            sponge: sponge,
+            suffix: suffix & ((1 << suffix_len) - 1) as u8,
+            suffix_len: suffix_len,
+            output_bits: output_bits,
+        }
+    }
+
+    pub fn output_bits(&self) -> uint {
+        self.output_bits
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.update_bits(data, data.len() * 8);
+    }
+
+    /// Absorb the first `bit_len` bits of `data`, bit i being bit i % 8 of
+    /// byte i / 8.
+    pub fn update_bits(&mut self, data: &[u8], bit_len: uint) {
+        assert!(bit_len <= data.len() * 8);
+
+        self.sponge.absorb(data, bit_len).unwrap();
+    }
+
+    /// The digest, `output_bits` long. A partial last byte holds its bits
+    /// in the least significant end.
+    pub fn finalize(self) -> ~[u8] {
+        assert!(self.output_bits != 0, "use finalize_xof for output of any length");
+
+        let output_bits = self.output_bits;
+        let mut reader = self.finalize_xof();
+        let mut out = vec::from_elem((output_bits + 7) / 8, 0u8);
+        reader.read_bits(out, output_bits);
+        out
+    }
+
+    /// Like `finalize`, but leaves the hasher empty and ready for the next
+    /// message.
+    pub fn finalize_reset(&mut self) -> ~[u8] {
+        assert!(self.output_bits != 0, "use finalize_xof for output of any length");
+
+        self.add_suffix();
+        let mut out = vec::from_elem((self.output_bits + 7) / 8, 0u8);
+        self.sponge.squeeze(out, self.output_bits).unwrap();
+        self.sponge.reset();
+        out
+    }
+
+    /// Whether the digest is `expected`, compared in constant time. For an
+    /// instance without a digest length, like SHAKE, whether the first
+    /// `expected.len()` bytes of output are.
+    pub fn verify(self, expected: &[u8]) -> bool {
+        if self.output_bits == 0 {
+            return self.finalize_xof().verify(expected);
+        }
+
+        let min_len = (self.output_bits + 7) / 8;
+        self.verify_truncated(expected, min_len)
+    }
+
+    /// Like `verify`, also accepting the first bytes of the digest, as long
+    /// as there are at least `min_len` of them.
+    pub fn verify_truncated(self, expected: &[u8], min_len: uint) -> bool {
+        if self.output_bits == 0 {
+            return self.finalize_xof().verify_truncated(expected, min_len);
+        }
+
+        verify_tag(self.finalize(), expected, min_len)
+    }
+
+    /// Pad the message and start reading output.
+    pub fn finalize_xof(self) -> Reader<P> {
+        let mut hasher = self;
+        hasher.add_suffix();
+        hasher.sponge.squeeze([], 0).unwrap();
+
+        Reader {
+            start: hasher.sponge.clone(),
+            sponge: hasher.sponge,
+            pos: 0,
+        }
+    }
+
+    /// Drop the input so far.
+    pub fn reset(&mut self) {
+        self.sponge.reset();
+    }
+
+    fn add_suffix(&mut self) {
+        if self.suffix_len != 0 {
+            self.sponge.absorb([self.suffix], self.suffix_len).unwrap();
+        }
+    }
+}
+
+impl<P: Permutation> Reader<P> {
+    /// Fill `out` with the next `out.len()` bytes of output.
+    pub fn read(&mut self, out: &mut [u8]) {
+        self.read_bits(out, out.len() * 8);
+    }
+
+    /// The next `bit_len` bits of output. A partial last byte holds its
+    /// bits in the least significant end, the rest cleared, and the next
+    /// read continues with the following bit.
+    pub fn read_bits(&mut self, out: &mut [u8], bit_len: uint) {
+        assert!(bit_len <= out.len() * 8);
+
+        self.sponge.squeeze(out, bit_len).unwrap();
+        self.pos += bit_len as u64;
+    }
+
+    /// Whether the next `expected.len()` bytes of output are `expected`,
+    /// compared in constant time. An empty `expected` is rejected without
+    /// reading.
+    pub fn verify(&mut self, expected: &[u8]) -> bool {
+        self.verify_truncated(expected, 1)
+    }
+
+    /// Like `verify`, rejecting an `expected` shorter than `min_len`
+    /// without reading.
+    pub fn verify_truncated(&mut self, expected: &[u8], min_len: uint) -> bool {
+        if !tag_len_ok(expected.len(), min_len) {
+            return false;
+        }
+
+        let mut out = vec::from_elem(expected.len(), 0u8);
+        self.read(out);
+        ct_eq(out, expected)
+    }
+}
+
+impl<P: Permutation + Clone> io::Writer for Hasher<P> {
+    fn write(&mut self, buf: &[u8]) {
+        self.update(buf);
+    }
+
+    fn flush(&mut self) {}
+}
+
+// The output never ends, so every read fills the whole buffer.
+impl<P: Permutation> io::Reader for Reader<P> {
+    fn read(&mut self, buf: &mut [u8]) -> Option<uint> {
+        self.read_bits(buf, buf.len() * 8);
+        Some(buf.len())
+    }
+
+    fn eof(&mut self) -> bool {
+        false
+    }
+}
+
+// Positions are in whole bytes; after a `read_bits` that ended mid-byte,
+// `tell` rounds down. There's no end to seek from.
+impl<P: Permutation + Clone> io::Seek for Reader<P> {
+    fn tell(&self) -> u64 {
+        self.pos / 8
+    }
+
+    fn seek(&mut self, pos: i64, style: io::SeekStyle) {
+        let target = match style {
+            io::SeekSet => pos,
+            io::SeekCur => (self.pos / 8) as i64 + pos,
+            io::SeekEnd => fail!("XOF output has no end to seek from")
+        };
+        assert!(target >= 0, "seek before the start of the output");
+
+        let target = 8 * target as u64;
+        if target < self.pos {
+            self.sponge = self.start.clone();
+            self.pos = 0;
+        }
+
+        let mut scratch = [0u8, ..200];
+        while self.pos < target {
+            let n = if target - self.pos < 1600 { (target - self.pos) as uint } else { 1600 };
+            self.read_bits(scratch, n);
+        }
+    }
+}
+
+// Through the inherent methods of the same names, which take precedence
+// over the trait ones. The fixed-output traits fail like `finalize` on an
+// instance without a digest length.
+impl<P: Permutation + Clone> digest::Update for Hasher<P> {
+    fn update(&mut self, data: &[u8]) {
+        self.update(data);
+    }
+}
+
+impl<P: Permutation + Clone> digest::Reset for Hasher<P> {
+    fn reset(&mut self) {
+        self.reset();
+    }
+}
+
+impl<P: Permutation + Clone> digest::OutputSizeUser for Hasher<P> {
+    fn output_size(&self) -> uint {
+        (self.output_bits + 7) / 8
+    }
+}
+
+impl<P: Permutation + Clone> digest::BlockSizeUser for Hasher<P> {
+    fn block_size(&self) -> uint {
+        self.sponge.rate / 8
+    }
+}
+
+impl<P: Permutation + Clone> digest::FixedOutput for Hasher<P> {
+    fn finalize_into(self, out: &mut [u8]) {
+        assert!(out.len() == (self.output_bits + 7) / 8);
+        out.copy_from(self.finalize());
+    }
+}
+
+impl<P: Permutation + Clone> digest::FixedOutputReset for Hasher<P> {
+    fn finalize_into_reset(&mut self, out: &mut [u8]) {
+        assert!(out.len() == (self.output_bits + 7) / 8);
+        out.copy_from(self.finalize_reset());
+    }
+}
+
+impl<P: Permutation + Clone> digest::ExtendableOutput<Reader<P>> for Hasher<P> {
+    fn finalize_xof(self) -> Reader<P> {
+        self.finalize_xof()
+    }
+}
+
+impl<P: Permutation + Clone> digest::HashMarker for Hasher<P> {}
+
+impl<P: Permutation> digest::XofReader for Reader<P> {
+    fn read(&mut self, out: &mut [u8]) {
+        self.read(out);
+    }
+}
+
+impl<P: Permutation + Clone> Clone for Hasher<P> {
+    fn clone(&self) -> Hasher<P> {
+        Hasher {
+            sponge: self.sponge.clone(),
+            suffix: self.suffix,
+            suffix_len: self.suffix_len,
+            output_bits: self.output_bits,
+        }
+    }
+}
+
+impl<P: Permutation + Clone> Clone for Reader<P> {
+    fn clone(&self) -> Reader<P> {
+        Reader {
+            sponge: self.sponge.clone(),
+            start: self.start.clone(),
+            pos: self.pos,
+        }
+    }
+}
+
+impl<P: Permutation> Zeroize for Hasher<P> {
+    fn zeroize(&mut self) {
+        self.sponge.zeroize();
+    }
+}
+
+impl<P: Permutation> Zeroize for Reader<P> {
+    fn zeroize(&mut self) {
+        self.sponge.zeroize();
+        self.start.zeroize();
+        self.pos = 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nist::Keccak;
+    use fips202::{Shake, shake128, shake256};
+    use extra::digest::Digest;
+    use std::vec;
+
+    #[test]
+    fn test_keccak_hasher() {
+        let msg = vec::from_fn(300, |i| i as u8);
+
+        let mut kc = Keccak::new(256);
+        kc.input(msg);
+        let mut expected = [0u8, ..32];
+        kc.result(expected);
+
+        let mut hasher = Keccak::hasher(256);
+        hasher.update(msg.slice_to(100));
+        hasher.update(msg.slice_from(100));
+        assert_eq!(hasher.finalize_reset().as_slice(), expected.as_slice());
+
+        hasher.update(msg);
+        assert_eq!(hasher.finalize().as_slice(), expected.as_slice());
+    }
+
+    #[test]
+    fn test_fork() {
+        let mut prefix = Shake::hasher(128);
+        prefix.update([0x42u8, ..1000]);
+
+        let mut a = prefix.clone();
+        let mut b = prefix;
+        a.update([1u8]);
+        b.update([2u8]);
+
+        let mut expected = [0u8, ..32];
+        let mut msg = vec::from_elem(1000, 0x42u8);
+        msg.push(1);
+        shake128(msg, expected);
+        let mut reader = a.finalize_xof();
+        assert!(reader.verify(expected));
+
+        msg[1000] = 2;
+        shake128(msg, expected);
+        let mut reader = b.finalize_xof();
+        assert!(reader.verify(expected));
+    }
+
+    #[test]
+    fn test_verify() {
+        let mut hasher = Keccak::hasher(384);
+        hasher.update([1u8, 2, 3]);
+        let digest = hasher.finalize_reset();
+
+        hasher.update([1u8, 2, 3]);
+        assert!(hasher.verify(digest));
+
+        let mut hasher = Keccak::hasher(384);
+        hasher.update([1u8, 2, 3]);
+        assert!(hasher.verify_truncated(digest.slice_to(24), 16));
+
+        let mut hasher = Shake::hasher(256);
+        hasher.update([1u8, 2, 3]);
+        let mut reader = hasher.finalize_xof();
+        let mut expected = [0u8, ..32];
+        shake256([1u8, 2, 3], expected);
+        assert!(!reader.verify_truncated(expected.slice_to(8), 16));
+        assert!(!reader.verify([]));
+        assert!(reader.verify(expected.slice_to(16)));
+        assert!(reader.verify_truncated(expected.slice_from(16), 16));
+
+        // A SHAKE hasher checks the start of its output.
+        let mut hasher = Shake::hasher(256);
+        hasher.update([1u8, 2, 3]);
+        assert!(hasher.verify(expected));
+
+        let mut hasher = Shake::hasher(256);
+        hasher.update([1u8, 2, 3]);
+        assert!(!hasher.clone().verify([]));
+        assert!(!hasher.clone().verify_truncated(expected.slice_to(8), 16));
+        assert!(hasher.verify_truncated(expected.slice_to(16), 16));
+    }
+
+    #[test]
+    fn test_io() {
+        use std::rt::io::{Writer, Seek, SeekSet, SeekCur};
+        use std::rt::io;
+
+        // `Reader::read` is also an inherent method; go through the trait.
+        fn io_read<R: io::Reader>(r: &mut R, buf: &mut [u8]) -> Option<uint> {
+            r.read(buf)
+        }
+
+        let msg = vec::from_fn(1000, |i| i as u8);
+        let mut expected = [0u8, ..600];
+        shake256(msg, expected);
+
+        let mut hasher = Shake::hasher(256);
+        for chunk in msg.chunk_iter(77) {
+            hasher.write(chunk);
+        }
+        let mut reader = hasher.finalize_xof();
+
+        let mut out = [0u8, ..100];
+        assert_eq!(io_read(&mut reader, out), Some(100));
+        assert_eq!(out.as_slice(), expected.slice_to(100));
+
+        reader.seek(450, SeekSet);
+        reader.read(out);
+        assert_eq!(out.as_slice(), expected.slice(450, 550));
+
+        reader.seek(-500, SeekCur);
+        assert_eq!(reader.tell(), 50);
+        reader.read(out);
+        assert_eq!(out.as_slice(), expected.slice(50, 150));
+    }
+
+    #[test]
+    fn test_shake_reader() {
+        let msg = vec::from_elem(500, 0x3cu8);
+        let mut expected = [0u8, ..400];
+        shake128(msg, expected);
+
+        let mut hasher = Shake::hasher(128);
+        hasher.update(msg);
+        let mut reader = hasher.finalize_xof();
+        let mut out = [0u8, ..400];
+        reader.read(out.mut_slice(0, 7));
+        reader.read(out.mut_slice(7, 400));
+        assert_eq!(out.as_slice(), expected.as_slice());
+    }
+}
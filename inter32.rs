@@ -0,0 +1,200 @@
+use consts::*;
+
+// Keccak-f[1600] for 32-bit CPUs, using bit interleaving: each 64-bit lane
+// is held as two 32-bit words, one with its even-numbered bits and one with
+// its odd-numbered bits. A 64-bit rotation then becomes two 32-bit
+// rotations (swapping the words when the amount is odd), so the whole round
+// runs on native 32-bit operations instead of emulated 64-bit shifts.
+//
+// Word 2 * i holds the even bits of lane i and word 2 * i + 1 the odd bits.
+
+// ROUND_CONST split into (even bits, odd bits).
+static ROUND_CONST_INTERLEAVED: [(u32, u32), ..24] = [
+    (0x00000001, 0x00000000), (0x00000000, 0x00000089),
+    (0x00000000, 0x8000008B), (0x00000000, 0x80008080),
+    (0x00000001, 0x0000008B), (0x00000001, 0x00008000),
+    (0x00000001, 0x80008088), (0x00000001, 0x80000082),
+    (0x00000000, 0x0000000B), (0x00000000, 0x0000000A),
+    (0x00000001, 0x00008082), (0x00000000, 0x00008003),
+    (0x00000001, 0x0000808B), (0x00000001, 0x8000000B),
+    (0x00000001, 0x8000008A), (0x00000001, 0x80000081),
+    (0x00000000, 0x80000081), (0x00000000, 0x80000008),
+    (0x00000000, 0x00000083), (0x00000000, 0x80008003),
+    (0x00000001, 0x80008088), (0x00000000, 0x80000088),
+    (0x00000001, 0x00008000), (0x00000000, 0x80008082),
+];
+
+#[inline(always)]
+fn rol32(a: u32, offset: uint) -> u32 {
+    let offset = offset % 32;
+    if offset == 0 { a } else { (a << offset) | (a >> (32 - offset)) }
+}
+
+// Rotate the lane (even, odd) left by `offset` bits of the 64-bit lane.
+#[inline(always)]
+fn rol_interleaved(even: u32, odd: u32, offset: uint) -> (u32, u32) {
+    if offset % 2 == 0 {
+        (rol32(even, offset / 2), rol32(odd, offset / 2))
+    } else {
+        (rol32(odd, (offset + 1) / 2), rol32(even, offset / 2))
+    }
+}
+
+// Gather the even-numbered bits of `x` into the low 32 bits.
+#[inline]
+fn compact(x: u64) -> u32 {
+    let mut x = x & 0x5555555555555555;
+    x = (x | (x >> 1)) & 0x3333333333333333;
+    x = (x | (x >> 2)) & 0x0F0F0F0F0F0F0F0F;
+    x = (x | (x >> 4)) & 0x00FF00FF00FF00FF;
+    x = (x | (x >> 8)) & 0x0000FFFF0000FFFF;
+    x = (x | (x >> 16)) & 0x00000000FFFFFFFF;
+    x as u32
+}
+
+// Inverse of `compact`: spread the 32 bits of `x` onto the even bits.
+#[inline]
+fn spread(x: u32) -> u64 {
+    let mut x = x as u64;
+    x = (x | (x << 16)) & 0x0000FFFF0000FFFF;
+    x = (x | (x << 8)) & 0x00FF00FF00FF00FF;
+    x = (x | (x << 4)) & 0x0F0F0F0F0F0F0F0F;
+    x = (x | (x << 2)) & 0x3333333333333333;
+    x = (x | (x << 1)) & 0x5555555555555555;
+    x
+}
+
+#[inline]
+pub fn interleave(lane: u64) -> (u32, u32) {
+    (compact(lane), compact(lane >> 1))
+}
+
+#[inline]
+pub fn deinterleave(even: u32, odd: u32) -> u64 {
+    spread(even) | (spread(odd) << 1)
+}
+
+/// Apply Keccak-f[1600] to 50 interleaved words.
+pub fn permute_interleaved(state: &mut [u32]) {
+    assert_eq!(state.len(), 50);
+
+    let mut c = [0u32, ..10];
+    let mut d = [0u32, ..10];
+    let mut b = [0u32, ..50];
+
+    for round in range(0, ROUND_N) {
+        for x in range(0u, 5) {
+            for h in range(0u, 2) {
+                c[2 * x + h] = state[2 * x + h] ^ state[2 * (x + 5) + h]
+                    ^ state[2 * (x + 10) + h] ^ state[2 * (x + 15) + h]
+                    ^ state[2 * (x + 20) + h];
+            }
+        }
+
+        for x in range(0u, 5) {
+            let x1 = (x + 1) % 5;
+            let x4 = (x + 4) % 5;
+            let (e, o) = rol_interleaved(c[2 * x1], c[2 * x1 + 1], 1);
+            d[2 * x] = c[2 * x4] ^ e;
+            d[2 * x + 1] = c[2 * x4 + 1] ^ o;
+        }
+
+        for i in range(0u, 25) {
+            let x = i % 5;
+            let (e, o) = rol_interleaved(state[2 * i] ^ d[2 * x],
+                                         state[2 * i + 1] ^ d[2 * x + 1],
+                                         RHO_OFFSETS[i]);
+            b[2 * PI_DEST[i]] = e;
+            b[2 * PI_DEST[i] + 1] = o;
+        }
+
+        for y in range(0u, 5) {
+            for x in range(0u, 5) {
+                let i = x + 5 * y;
+                let i1 = (x + 1) % 5 + 5 * y;
+                let i2 = (x + 2) % 5 + 5 * y;
+                for h in range(0u, 2) {
+                    state[2 * i + h] = b[2 * i + h] ^ (!b[2 * i1 + h] & b[2 * i2 + h]);
+                }
+            }
+        }
+
+        let (rc_even, rc_odd) = ROUND_CONST_INTERLEAVED[round];
+        state[0] ^= rc_even;
+        state[1] ^= rc_odd;
+    }
+}
+
+/// Apply Keccak-f[1600] to 25 ordinary lanes, interleaving them on the way
+/// in and out.
+pub fn permute_on_words(state: &mut [u64]) {
+    let mut words = [0u32, ..50];
+
+    for i in range(0, 25) {
+        let (e, o) = interleave(state[i]);
+        words[2 * i] = e;
+        words[2 * i + 1] = o;
+    }
+
+    permute_interleaved(words);
+
+    for i in range(0, 25) {
+        state[i] = deinterleave(words[2 * i], words[2 * i + 1]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use consts::ROUND_CONST;
+    use opt64;
+    use extra::test::BenchHarness;
+
+    #[test]
+    fn test_interleave() {
+        use std::rand::{weak_rng, RngUtil};
+
+        let mut rng = weak_rng();
+
+        for _ in range(0, 100) {
+            let lane = rng.gen::<u64>();
+            let (e, o) = interleave(lane);
+            assert_eq!(deinterleave(e, o), lane);
+        }
+
+        for i in range(0, 24) {
+            assert_eq!(interleave(ROUND_CONST[i]), super::ROUND_CONST_INTERLEAVED[i]);
+        }
+    }
+
+    #[test]
+    fn test_against_opt64() {
+        use std::rand::{weak_rng, RngUtil};
+
+        let mut rng = weak_rng();
+
+        for _ in range(0, 100) {
+            let mut inter = [0u64, ..25];
+            for i in range(0, 25) {
+                inter[i] = rng.gen();
+            }
+            let mut portable = inter;
+
+            permute_on_words(inter);
+            opt64::permute_on_words(portable);
+
+            assert_eq!(inter.as_slice(), portable.as_slice());
+        }
+    }
+
+    #[bench]
+    fn bench_permute_interleaved(bh: &mut BenchHarness) {
+        let mut state = [0u32, ..50];
+
+        do bh.iter {
+            permute_interleaved(state);
+        }
+
+        bh.bytes = 200;
+    }
+}
@@ -4,9 +4,30 @@ extern mod extra;
 use extra::digest::*;
 
 pub mod consts;
+pub mod lanes;
 pub mod reference;
+pub mod opt64;
+pub mod inter32;
+pub mod backend;
+pub mod permutation;
+#[cfg(target_arch = "x86_64", not(portable))]
+pub mod simd;
 pub mod nist;
+pub mod bitstring;
 pub mod sponge;
+pub mod direct;
+pub mod duplex;
+pub mod hasher;
+pub mod digest;
+pub mod fips202;
+pub mod lms;
+pub mod slh_dsa;
+pub mod multi;
+pub mod zeroize;
+pub mod ct;
+pub mod midstate;
+pub mod keyed;
+pub mod output;
 
 fn main() {
     use nist::*;
@@ -0,0 +1,259 @@
+// Keyed 64-bit hashing of table keys, for hash tables that hold keys an
+// attacker picks. A `RandomState` draws a secret key and a `KeyedHasher`
+// computes a MAC of the key bytes under it: the sponge on Keccak-p[1600,
+// 12] (the permutation of KangarooTwelve) with a 256-bit capacity, keyed
+// by absorbing the secret as its whole first block.
+//
+// Because the key fills a block of its own, `RandomState` permutes it once
+// and every hasher starts from a copy of that state, so a table key that
+// fits in the rest costs a single permutation.
+//
+// `KeyedHasher` implements `std::hash::Streaming`, but `HashMap` can't use
+// it: on this toolchain the map hashes its keys with SipHash under keys of
+// its own, through `IterBytes`, and takes no hasher parameter. Tables that
+// want this hash key on it themselves, keeping the entries that collide:
+//
+//     let state = RandomState::new();
+//     let mut table: HashMap<u64, ~[(~str, uint)]> = HashMap::new();
+//     table.find_or_insert(state.hash(&name), ~[]).push((name, value));
+//
+// and look a name up by scanning `table.find(&state.hash(&name))`.
+
+use sponge::Sponge;
+use permutation::KeccakP1600;
+use lanes::load_lane;
+use zeroize::{Zeroize, zeroize_slice};
+use std::hash::{IterBytes, Streaming};
+
+static ROUNDS: uint = 12;
+static RATE: uint = 1344;
+static CAPACITY: uint = 256;
+static RATE_IN_BYTES: uint = RATE / 8;
+
+pub static KEY_LEN: uint = 16;
+
+/// Makes `KeyedHasher`s that share one secret key; one per table.
+pub struct RandomState {
+    priv keyed: Sponge<KeccakP1600>,
+}
+
+/// Hashes the bytes written to it to a `u64` under a `RandomState`'s key.
+/// Splitting the input across calls doesn't change the result.
+pub struct KeyedHasher {
+    priv keyed: Sponge<KeccakP1600>,
+    priv sponge: Sponge<KeccakP1600>,
+}
+
+impl RandomState {
+    /// A fresh key from the task's secure random generator.
+    pub fn new() -> RandomState {
+        use std::rand::{rng, RngUtil};
+
+        let mut bytes = rng().gen_bytes(KEY_LEN);
+        let mut key = [0u8, ..KEY_LEN];
+        for i in range(0, KEY_LEN) {
+            key[i] = bytes[i];
+        }
+        let state = RandomState::with_key(&key);
+
+        zeroize_slice(bytes);
+        zeroize_slice(key);
+        state
+    }
+
+    /// A given key, for hashes that must be reproducible.
+    pub fn with_key(key: &[u8, ..KEY_LEN]) -> RandomState {
+        let mut sponge = Sponge::new(KeccakP1600::new(ROUNDS), RATE, CAPACITY).unwrap();
+        let mut block = [0u8, ..RATE_IN_BYTES];
+        for i in range(0, KEY_LEN) {
+            block[i] = key[i];
+        }
+        sponge.absorb(block, RATE).unwrap();
+        zeroize_slice(block);
+
+        RandomState { keyed: sponge }
+    }
+
+    pub fn build_hasher(&self) -> KeyedHasher {
+        KeyedHasher { keyed: self.keyed.clone(), sponge: self.keyed.clone() }
+    }
+
+    /// The hash of `value`'s `IterBytes` encoding.
+    pub fn hash<T: IterBytes>(&self, value: &T) -> u64 {
+        let mut hasher = self.build_hasher();
+        value.iter_bytes(true, |bytes| {
+            hasher.write(bytes);
+            true
+        });
+        hasher.finish()
+    }
+}
+
+impl KeyedHasher {
+    pub fn write(&mut self, bytes: &[u8]) {
+        self.sponge.absorb(bytes, bytes.len() * 8).unwrap();
+    }
+
+    pub fn write_u8(&mut self, x: u8) {
+        self.write([x]);
+    }
+
+    pub fn write_u16(&mut self, x: u16) {
+        self.write_le(x as u64, 2);
+    }
+
+    pub fn write_u32(&mut self, x: u32) {
+        self.write_le(x as u64, 4);
+    }
+
+    pub fn write_u64(&mut self, x: u64) {
+        self.write_le(x, 8);
+    }
+
+    /// `x` as 8 bytes, so the hash is the same on 32- and 64-bit hosts.
+    pub fn write_uint(&mut self, x: uint) {
+        self.write_le(x as u64, 8);
+    }
+
+    /// The hash of everything written so far. More can be written after.
+    pub fn finish(&self) -> u64 {
+        let mut sponge = self.sponge.clone();
+        let mut out = [0u8, ..8];
+        sponge.squeeze(out, 64).unwrap();
+        load_lane(out)
+    }
+
+    fn write_le(&mut self, x: u64, len: uint) {
+        let mut bytes = [0u8, ..8];
+        for i in range(0, len) {
+            bytes[i] = (x >> (8 * i)) as u8;
+        }
+        self.write(bytes.slice_to(len));
+    }
+}
+
+impl Streaming for KeyedHasher {
+    fn input(&mut self, bytes: &[u8]) {
+        self.write(bytes);
+    }
+
+    /// `finish` as 8 little-endian bytes.
+    fn result_bytes(&mut self) -> ~[u8] {
+        let h = self.finish();
+        range(0u, 8).map(|i| (h >> (8 * i)) as u8).collect()
+    }
+
+    fn result_str(&mut self) -> ~str {
+        let mut s = ~"";
+        for &b in self.result_bytes().iter() {
+            s.push_str(fmt!("%02x", b as uint));
+        }
+        s
+    }
+
+    fn result_u64(&mut self) -> u64 {
+        self.finish()
+    }
+
+    /// Start over under the same key.
+    fn reset(&mut self) {
+        self.sponge = self.keyed.clone();
+    }
+}
+
+// Under `--cfg zeroize` the sponges wipe themselves when dropped, which
+// covers both types; these are for wiping earlier.
+impl Zeroize for RandomState {
+    fn zeroize(&mut self) {
+        self.keyed.zeroize();
+    }
+}
+
+impl Zeroize for KeyedHasher {
+    fn zeroize(&mut self) {
+        self.keyed.zeroize();
+        self.sponge.zeroize();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use extra::test::BenchHarness;
+
+    static KEY: [u8, ..16] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07,
+        0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+    ];
+
+    #[test]
+    fn test_streaming() {
+        let state = RandomState::with_key(&KEY);
+
+        let mut a = state.build_hasher();
+        a.write([1u8, 2, 3, 4, 5, 6, 7, 8, 9]);
+
+        let mut b = state.build_hasher();
+        b.write_u8(1);
+        b.write([2u8, 3]);
+        b.write_u16(0x0504);
+        b.write_u32(0x09080706);
+        assert_eq!(a.finish(), b.finish());
+
+        a.write_u64(0);
+        assert!(a.finish() != b.finish());
+    }
+
+    #[test]
+    fn test_streaming_trait() {
+        use std::hash::Streaming;
+
+        let state = RandomState::with_key(&KEY);
+        let mut hasher = state.build_hasher();
+        hasher.input([1u8, 2, 3]);
+        let h = hasher.result_u64();
+        let mut direct = state.build_hasher();
+        direct.write([1u8, 2, 3]);
+        assert_eq!(h, direct.finish());
+
+        let bytes = hasher.result_bytes();
+        assert_eq!(bytes.len(), 8);
+        assert_eq!(bytes[0] as u64, h & 0xff);
+        assert_eq!(bytes[7] as u64, h >> 56);
+        let hex = hasher.result_str();
+        assert_eq!(hex.len(), 16);
+        assert_eq!(hex.slice_to(2).to_owned(), fmt!("%02x", bytes[0] as uint));
+
+        hasher.reset();
+        hasher.input([1u8, 2, 3]);
+        assert_eq!(hasher.result_u64(), h);
+    }
+
+    #[test]
+    fn test_keys() {
+        let fixed = RandomState::with_key(&KEY);
+        assert_eq!(fixed.hash(&~"key"), RandomState::with_key(&KEY).hash(&~"key"));
+        assert!(fixed.hash(&~"key") != fixed.hash(&~"kez"));
+
+        let mut other = KEY;
+        other[15] ^= 1;
+        assert!(fixed.hash(&~"key") != RandomState::with_key(&other).hash(&~"key"));
+
+        let a = RandomState::new();
+        let b = RandomState::new();
+        assert!(a.hash(&12345u) != b.hash(&12345u));
+    }
+
+    #[bench]
+    fn bench_short_key(bh: &mut BenchHarness) {
+        let state = RandomState::with_key(&KEY);
+
+        do bh.iter {
+            let mut hasher = state.build_hasher();
+            hasher.write_u64(0x0123456789abcdef);
+            hasher.finish();
+        }
+
+        bh.bytes = 8;
+    }
+}
@@ -0,0 +1,159 @@
+// The 1600-bit state is kept as 25 u64 lanes, lane x + 5 * y. Its byte
+// view, used to absorb and extract, is the one from the Keccak and FIPS 202
+// specifications: byte i of the state is byte i % 8 of lane i / 8, least
+// significant byte first. Every conversion between the two goes through
+// this module, so results are the same on little- and big-endian hosts.
+
+#[inline]
+pub fn load_lane(bytes: &[u8]) -> u64 {
+    let mut lane = 0u64;
+    for i in range(0u, 8) {
+        lane |= bytes[i] as u64 << (8 * i);
+    }
+    lane
+}
+
+#[inline]
+pub fn store_lane(lane: u64, bytes: &mut [u8]) {
+    for i in range(0u, 8) {
+        bytes[i] = (lane >> (8 * i)) as u8;
+    }
+}
+
+pub fn load_lanes(state: &[u8], lanes: &mut [u64]) {
+    for i in range(0, 25) {
+        lanes[i] = load_lane(state.slice(8 * i, 8 * i + 8));
+    }
+}
+
+pub fn store_lanes(lanes: &[u64], state: &mut [u8]) {
+    for i in range(0, 25) {
+        store_lane(lanes[i], state.mut_slice(8 * i, 8 * i + 8));
+    }
+}
+
+/// XOR `data` into the byte view of `lanes`, starting at byte `offset`.
+pub fn xor_bytes(lanes: &mut [u64], data: &[u8], offset: uint) {
+    assert!(offset + data.len() <= 8 * lanes.len());
+
+    let mut j = 0;
+    while j < data.len() && (offset + j) % 8 != 0 {
+        lanes[(offset + j) / 8] ^= data[j] as u64 << (8 * ((offset + j) % 8));
+        j += 1;
+    }
+    while j + 8 <= data.len() {
+        lanes[(offset + j) / 8] ^= load_lane(data.slice(j, j + 8));
+        j += 8;
+    }
+    while j < data.len() {
+        lanes[(offset + j) / 8] ^= data[j] as u64 << (8 * ((offset + j) % 8));
+        j += 1;
+    }
+}
+
+/// Copy `out.len()` bytes of the byte view of `lanes`, starting at byte
+/// `offset`, to `out`.
+pub fn extract_bytes(lanes: &[u64], out: &mut [u8], offset: uint) {
+    assert!(offset + out.len() <= 8 * lanes.len());
+
+    for j in range(0, out.len()) {
+        out[j] = (lanes[(offset + j) / 8] >> (8 * ((offset + j) % 8))) as u8;
+    }
+}
+
+/// Replace `data.len()` bytes of the byte view of `lanes`, starting at byte
+/// `offset`, with `data`.
+pub fn overwrite_bytes(lanes: &mut [u64], data: &[u8], offset: uint) {
+    assert!(offset + data.len() <= 8 * lanes.len());
+
+    for j in range(0, data.len()) {
+        let k = offset + j;
+        let shift = 8 * (k % 8);
+        lanes[k / 8] = (lanes[k / 8] & !(0xFFu64 << shift)) | (data[j] as u64 << shift);
+    }
+}
+
+/// Set the first `byte_count` bytes of the byte view of `lanes` to zero.
+pub fn overwrite_with_zeroes(lanes: &mut [u64], byte_count: uint) {
+    assert!(byte_count <= 8 * lanes.len());
+
+    for i in range(0, byte_count / 8) {
+        lanes[i] = 0;
+    }
+    if byte_count % 8 != 0 {
+        lanes[byte_count / 8] &= !0u64 << (8 * (byte_count % 8));
+    }
+}
+
+/// Write `input` XORed with the byte view of `lanes`, starting at byte
+/// `offset`, to `out`.
+pub fn extract_and_add_bytes(lanes: &[u64], input: &[u8], out: &mut [u8], offset: uint) {
+    assert!(input.len() == out.len());
+    assert!(offset + out.len() <= 8 * lanes.len());
+
+    for j in range(0, out.len()) {
+        let k = offset + j;
+        out[j] = input[j] ^ (lanes[k / 8] >> (8 * (k % 8))) as u8;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_byte_order() {
+        let bytes = [0x01u8, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF];
+        assert_eq!(load_lane(bytes), 0xEFCDAB8967452301);
+
+        let mut out = [0u8, ..8];
+        store_lane(0xEFCDAB8967452301, out);
+        assert_eq!(out.as_slice(), bytes.as_slice());
+    }
+
+    #[test]
+    fn test_overwrite() {
+        let mut lanes = [0x0123456789ABCDEFu64, ..25];
+
+        overwrite_bytes(lanes, [0xAAu8, 0xBB, 0xCC], 6);
+        assert_eq!(lanes[0], 0xBBAA456789ABCDEF);
+        assert_eq!(lanes[1], 0x0123456789ABCDCC);
+
+        overwrite_with_zeroes(lanes, 11);
+        assert_eq!(lanes[0], 0);
+        assert_eq!(lanes[1], 0x0123456789000000);
+        assert_eq!(lanes[2], 0x0123456789ABCDEF);
+
+        let mut out = [0u8, ..3];
+        extract_and_add_bytes(lanes, [0xFFu8, 0xFF, 0xFF], out, 10);
+        assert_eq!(out.as_slice(), [0xFFu8, 0x76, 0x98].as_slice());
+    }
+
+    #[test]
+    fn test_partial_lanes() {
+        use std::rand::{weak_rng, RngUtil};
+
+        let mut rng = weak_rng();
+        let data = rng.gen_bytes(200);
+        let mut lanes = [0u64, ..25];
+        load_lanes(data, lanes);
+
+        for &offset in [0u, 3, 8, 13].iter() {
+            for len in range(0u, 201 - offset) {
+                let end = offset + len;
+
+                let mut out = [0u8, ..200];
+                extract_bytes(lanes, out.mut_slice_to(len), offset);
+                assert_eq!(out.slice_to(len), data.slice(offset, end));
+
+                let mut cleared = lanes;
+                xor_bytes(cleared, data.slice(offset, end), offset);
+                let mut bytes = [0u8, ..200];
+                store_lanes(cleared, bytes);
+                assert_eq!(bytes.slice_to(offset), data.slice_to(offset));
+                assert!(bytes.slice(offset, end).iter().all(|&b| b == 0));
+                assert_eq!(bytes.slice_from(end), data.slice_from(end));
+            }
+        }
+    }
+}
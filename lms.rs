@@ -0,0 +1,1132 @@
+use fips202::Shake;
+use zeroize::{Zeroize, zeroize_slice};
+use std::vec;
+
+// Leighton-Micali signatures (RFC 8554) and the HSS multi-tree scheme,
+// restricted to the SHAKE256/192 and SHAKE256/256 parameter sets of
+// NIST SP 800-208.
+
+pub static LMS_SHAKE_M32_H5: u32 = 0x0000000F;
+pub static LMS_SHAKE_M32_H10: u32 = 0x00000010;
+pub static LMS_SHAKE_M32_H15: u32 = 0x00000011;
+pub static LMS_SHAKE_M32_H20: u32 = 0x00000012;
+pub static LMS_SHAKE_M32_H25: u32 = 0x00000013;
+pub static LMS_SHAKE_M24_H5: u32 = 0x00000014;
+pub static LMS_SHAKE_M24_H10: u32 = 0x00000015;
+pub static LMS_SHAKE_M24_H15: u32 = 0x00000016;
+pub static LMS_SHAKE_M24_H20: u32 = 0x00000017;
+pub static LMS_SHAKE_M24_H25: u32 = 0x00000018;
+
+pub static LMOTS_SHAKE_N32_W1: u32 = 0x00000009;
+pub static LMOTS_SHAKE_N32_W2: u32 = 0x0000000A;
+pub static LMOTS_SHAKE_N32_W4: u32 = 0x0000000B;
+pub static LMOTS_SHAKE_N32_W8: u32 = 0x0000000C;
+pub static LMOTS_SHAKE_N24_W1: u32 = 0x0000000D;
+pub static LMOTS_SHAKE_N24_W2: u32 = 0x0000000E;
+pub static LMOTS_SHAKE_N24_W4: u32 = 0x0000000F;
+pub static LMOTS_SHAKE_N24_W8: u32 = 0x00000010;
+
+/// The tallest tree a private key is built for. The whole tree is kept in
+/// memory, so a key of height h costs 2^h LM-OTS key generations up front
+/// and 2^(h+1) * m bytes: 2 MiB at h = 15, m = 32. Heights 20 and 25 can
+/// still be verified.
+pub static MAX_PRIVATE_HEIGHT: uint = 15;
+
+static D_PBLC: u16 = 0x8080;
+static D_MESG: u16 = 0x8181;
+static D_LEAF: u16 = 0x8282;
+static D_INTR: u16 = 0x8383;
+
+// Indices for deriving HSS child keys from the parent's seed, chosen
+// above any chain index `i < p` used for LM-OTS private elements.
+static D_CHILD_SEED: u16 = 0xFFFE;
+static D_CHILD_I: u16 = 0xFFFF;
+
+#[deriving(Eq,ToStr,Clone)]
+pub enum LmsError {
+    UnsupportedType,
+    BadKeyMaterial,
+    KeyExhausted,
+    /// The caller's `persist` couldn't store the key's new state.
+    PersistFailed,
+    /// A private key taller than `MAX_PRIVATE_HEIGHT`.
+    TreeTooLarge,
+}
+
+#[deriving(Eq,Clone)]
+struct LmotsParams {
+    id: u32,
+    n: uint,
+    w: uint,
+    p: uint,
+    ls: uint,
+    u: uint,
+}
+
+#[deriving(Eq,Clone)]
+struct LmsParams {
+    id: u32,
+    m: uint,
+    h: uint,
+}
+
+fn lmots_params(id: u32) -> Option<LmotsParams> {
+    let (n, w) = match id {
+        0x09 .. 0x0C => (32u, 1u << (id - 0x09) as uint),
+        0x0D .. 0x10 => (24u, 1u << (id - 0x0D) as uint),
+        _ => return None
+    };
+
+    // RFC 8554, Appendix B
+    let u = (8 * n + w - 1) / w;
+    let mut bits = 0u;
+    let mut max_sum = ((1u << w) - 1) * u;
+    while max_sum > 0 {
+        bits += 1;
+        max_sum >>= 1;
+    }
+    let v = (bits + w - 1) / w;
+
+    Some(LmotsParams { id: id, n: n, w: w, p: u + v, ls: 16 - v * w, u: u })
+}
+
+fn lms_params(id: u32) -> Option<LmsParams> {
+    match id {
+        0x0F .. 0x13 => Some(LmsParams { id: id, m: 32, h: 5 * ((id - 0x0F) as uint + 1) }),
+        0x14 .. 0x18 => Some(LmsParams { id: id, m: 24, h: 5 * ((id - 0x14) as uint + 1) }),
+        _ => None
+    }
+}
+
+fn params(lms_type: u32, ots_type: u32) -> Option<(LmsParams, LmotsParams)> {
+    match (lms_params(lms_type), lmots_params(ots_type)) {
+        (Some(lms), Some(ots)) if lms.m == ots.n => Some((lms, ots)),
+        _ => None
+    }
+}
+
+fn u32str(x: u32) -> [u8, ..4] {
+    [(x >> 24) as u8, (x >> 16) as u8, (x >> 8) as u8, x as u8]
+}
+
+fn u16str(x: u16) -> [u8, ..2] {
+    [(x >> 8) as u8, x as u8]
+}
+
+fn read_u32(b: &[u8]) -> u32 {
+    (b[0] as u32 << 24) | (b[1] as u32 << 16) | (b[2] as u32 << 8) | (b[3] as u32)
+}
+
+fn finish(sh: &mut Shake, n: uint) -> ~[u8] {
+    let mut out = vec::from_elem(n, 0u8);
+    sh.squeeze(out).unwrap();
+    out
+}
+
+fn copy_into(dst: &mut [u8], src: &[u8]) {
+    for (d, s) in dst.mut_iter().zip(src.iter()) {
+        *d = *s;
+    }
+}
+
+/// H(I || u32str(q) || u16str(i) || u8str(0xff) || SEED), the pseudorandom
+/// key derivation of RFC 8554, Appendix A.
+fn derive(n: uint, id: &[u8], q: u32, i: u16, seed: &[u8]) -> ~[u8] {
+    let mut sh = Shake::new(256);
+    sh.input(id).unwrap();
+    sh.input(u32str(q)).unwrap();
+    sh.input(u16str(i)).unwrap();
+    sh.input(&[0xffu8]).unwrap();
+    sh.input(seed).unwrap();
+    finish(&mut sh, n)
+}
+
+fn node_hash(m: uint, id: &[u8], r: uint, tag: u16, left: &[u8], right: &[u8]) -> ~[u8] {
+    let mut sh = Shake::new(256);
+    sh.input(id).unwrap();
+    sh.input(u32str(r as u32)).unwrap();
+    sh.input(u16str(tag)).unwrap();
+    sh.input(left).unwrap();
+    sh.input(right).unwrap();
+    finish(&mut sh, m)
+}
+
+fn coef(s: &[u8], i: uint, w: uint) -> uint {
+    ((s[i * w / 8] as uint) >> (8 - (w * (i % (8 / w)) + w))) & ((1u << w) - 1)
+}
+
+fn chain(ots: &LmotsParams, id: &[u8], q: u32, i: uint,
+         start: uint, end: uint, x: &[u8]) -> ~[u8] {
+    let mut tmp = x.to_owned();
+
+    for j in range(start, end) {
+        let mut sh = Shake::new(256);
+        sh.input(id).unwrap();
+        sh.input(u32str(q)).unwrap();
+        sh.input(u16str(i as u16)).unwrap();
+        sh.input(&[j as u8]).unwrap();
+        sh.input(tmp).unwrap();
+        tmp = finish(&mut sh, ots.n);
+    }
+
+    tmp
+}
+
+/// Q || Cksm(Q): the base-2^w digits that select how far along each chain
+/// the signature reveals.
+fn message_digits(ots: &LmotsParams, id: &[u8], q: u32, c: &[u8], msg: &[u8]) -> ~[u8] {
+    let mut sh = Shake::new(256);
+    sh.input(id).unwrap();
+    sh.input(u32str(q)).unwrap();
+    sh.input(u16str(D_MESG)).unwrap();
+    sh.input(c).unwrap();
+    sh.input(msg).unwrap();
+    let mut digits = finish(&mut sh, ots.n);
+
+    let mut sum = 0u;
+    for i in range(0, ots.u) {
+        sum += (1u << ots.w) - 1 - coef(digits, i, ots.w);
+    }
+    digits.push_all(u16str((sum << ots.ls) as u16));
+
+    digits
+}
+
+fn ots_public(ots: &LmotsParams, id: &[u8], q: u32, seed: &[u8]) -> ~[u8] {
+    let top = (1u << ots.w) - 1;
+    let mut sh = Shake::new(256);
+    sh.input(id).unwrap();
+    sh.input(u32str(q)).unwrap();
+    sh.input(u16str(D_PBLC)).unwrap();
+
+    for i in range(0, ots.p) {
+        let x = derive(ots.n, id, q, i as u16, seed);
+        sh.input(chain(ots, id, q, i, 0, top, x)).unwrap();
+    }
+
+    finish(&mut sh, ots.n)
+}
+
+fn ots_sign(ots: &LmotsParams, id: &[u8], q: u32, seed: &[u8],
+            c: &[u8], msg: &[u8]) -> ~[u8] {
+    let digits = message_digits(ots, id, q, c, msg);
+
+    let mut sig = vec::with_capacity(ots_sig_len(ots));
+    sig.push_all(u32str(ots.id));
+    sig.push_all(c);
+
+    for i in range(0, ots.p) {
+        let x = derive(ots.n, id, q, i as u16, seed);
+        sig.push_all(chain(ots, id, q, i, 0, coef(digits, i, ots.w), x));
+    }
+
+    sig
+}
+
+/// Public key candidate Kc computed from an LM-OTS signature (RFC 8554,
+/// Algorithm 4b). `sig` must already have the correct length and type.
+fn ots_candidate(ots: &LmotsParams, id: &[u8], q: u32, msg: &[u8], sig: &[u8]) -> ~[u8] {
+    let n = ots.n;
+    let top = (1u << ots.w) - 1;
+    let digits = message_digits(ots, id, q, sig.slice(4, 4 + n), msg);
+
+    let mut sh = Shake::new(256);
+    sh.input(id).unwrap();
+    sh.input(u32str(q)).unwrap();
+    sh.input(u16str(D_PBLC)).unwrap();
+
+    for i in range(0, ots.p) {
+        let y = sig.slice(4 + (i + 1) * n, 4 + (i + 2) * n);
+        sh.input(chain(ots, id, q, i, coef(digits, i, ots.w), top, y)).unwrap();
+    }
+
+    finish(&mut sh, n)
+}
+
+fn ots_sig_len(ots: &LmotsParams) -> uint {
+    4 + ots.n + ots.p * ots.n
+}
+
+/// Length of the LMS signature at the start of `bytes`, read from the
+/// types it carries, or `None` if it is truncated or of unknown type.
+fn lms_sig_len_at(bytes: &[u8]) -> Option<uint> {
+    if bytes.len() < 8 {
+        return None;
+    }
+    let ots = match lmots_params(read_u32(bytes.slice(4, 8))) {
+        Some(ots) => ots,
+        None => return None
+    };
+    let off = 4 + ots_sig_len(&ots);
+    if bytes.len() < off + 4 {
+        return None;
+    }
+    let lms = match lms_params(read_u32(bytes.slice(off, off + 4))) {
+        Some(lms) => lms,
+        None => return None
+    };
+    let len = off + 4 + lms.h * lms.m;
+
+    if bytes.len() < len { None } else { Some(len) }
+}
+
+/// An LMS private key. Each signature consumes one leaf. `sign` reserves
+/// the leaf and hands the advanced state to the caller to store before it
+/// returns the signature, so a key restored with `from_bytes` after a
+/// restart never reuses a one-time key.
+pub struct LmsPrivateKey {
+    priv lms: LmsParams,
+    priv ots: LmotsParams,
+    priv id: ~[u8],
+    priv seed: ~[u8],
+    priv q: u32,
+    // Nodes T[1] .. T[2^(h+1) - 1], m bytes each, at offset r * m.
+    priv tree: ~[u8],
+}
+
+impl LmsPrivateKey {
+    /// Derive a key from a 16-byte identifier `id` and an n-byte `seed`.
+    /// The whole Merkle tree is computed and kept; heights above
+    /// `MAX_PRIVATE_HEIGHT` fail with `TreeTooLarge`.
+    pub fn new(lms_type: u32, ots_type: u32,
+               id: &[u8], seed: &[u8]) -> Result<LmsPrivateKey, LmsError> {
+        let (lms, ots) = match params(lms_type, ots_type) {
+            Some(p) => p,
+            None => return Err(UnsupportedType)
+        };
+
+        if lms.h > MAX_PRIVATE_HEIGHT {
+            return Err(TreeTooLarge);
+        }
+
+        if id.len() != 16 || seed.len() != ots.n {
+            return Err(BadKeyMaterial);
+        }
+
+        let m = lms.m;
+        let leaves = 1u << lms.h;
+        let mut tree = vec::from_elem(2 * leaves * m, 0u8);
+
+        for r in range(leaves, 2 * leaves) {
+            let k = ots_public(&ots, id, (r - leaves) as u32, seed);
+            let node = node_hash(m, id, r, D_LEAF, k, []);
+            copy_into(tree.mut_slice(r * m, (r + 1) * m), node);
+        }
+
+        let mut r = leaves - 1;
+        while r > 0 {
+            let node = node_hash(m, id, r, D_INTR,
+                tree.slice(2 * r * m, (2 * r + 1) * m),
+                tree.slice((2 * r + 1) * m, (2 * r + 2) * m));
+            copy_into(tree.mut_slice(r * m, (r + 1) * m), node);
+            r -= 1;
+        }
+
+        Ok(LmsPrivateKey {
+            lms: lms,
+            ots: ots,
+            id: id.to_owned(),
+            seed: seed.to_owned(),
+            q: 0,
+            tree: tree,
+        })
+    }
+
+    /// Generate a key with a random identifier and seed.
+    pub fn generate(lms_type: u32, ots_type: u32) -> Result<LmsPrivateKey, LmsError> {
+        use std::rand::{rng, RngUtil};
+
+        let n = match params(lms_type, ots_type) {
+            Some((_, ots)) => ots.n,
+            None => return Err(UnsupportedType)
+        };
+
+        let mut r = rng();
+        let id = r.gen_bytes(16);
+        let seed = r.gen_bytes(n);
+
+        LmsPrivateKey::new(lms_type, ots_type, id, seed)
+    }
+
+    /// Restore a key saved with `to_bytes`, continuing at its saved leaf.
+    /// The tree is recomputed, at the cost of generating a new key.
+    pub fn from_bytes(bytes: &[u8]) -> Result<LmsPrivateKey, LmsError> {
+        if bytes.len() < 12 {
+            return Err(BadKeyMaterial);
+        }
+
+        let (lms, ots) = match params(read_u32(bytes.slice(0, 4)), read_u32(bytes.slice(4, 8))) {
+            Some(p) => p,
+            None => return Err(UnsupportedType)
+        };
+        let q = read_u32(bytes.slice(8, 12));
+        if bytes.len() != 28 + ots.n || q as uint > 1u << lms.h {
+            return Err(BadKeyMaterial);
+        }
+
+        match LmsPrivateKey::new(lms.id, ots.id, bytes.slice(12, 28), bytes.slice_from(28)) {
+            Ok(key) => {
+                let mut key = key;
+                key.q = q;
+                Ok(key)
+            }
+            Err(e) => Err(e)
+        }
+    }
+
+    /// u32str(type) || u32str(otstype) || u32str(q) || I || SEED, where q is
+    /// the next unused leaf. This is secret: it holds the seed.
+    pub fn to_bytes(&self) -> ~[u8] {
+        let mut out = vec::with_capacity(28 + self.ots.n);
+        out.push_all(u32str(self.lms.id));
+        out.push_all(u32str(self.ots.id));
+        out.push_all(u32str(self.q));
+        out.push_all(self.id);
+        out.push_all(self.seed);
+        out
+    }
+
+    pub fn public_key(&self) -> LmsPublicKey {
+        LmsPublicKey {
+            lms: self.lms.clone(),
+            ots: self.ots.clone(),
+            id: self.id.clone(),
+            root: self.node(1).to_owned(),
+        }
+    }
+
+    /// Index of the next leaf to be used.
+    pub fn q(&self) -> u32 {
+        self.q
+    }
+
+    /// Number of signatures this key can still produce.
+    pub fn remaining(&self) -> uint {
+        (1u << self.lms.h) - self.q as uint
+    }
+
+    /// Sign `msg` with the next leaf. The leaf is marked used first and
+    /// `persist` is called with the new state, as `to_bytes` returns it;
+    /// only if it returns true, meaning the state is stored, is the
+    /// signature computed. Otherwise the leaf stays used and the result is
+    /// `PersistFailed`.
+    pub fn sign(&mut self, msg: &[u8], persist: &fn(&[u8]) -> bool) -> Result<~[u8], LmsError> {
+        use std::rand::{rng, RngUtil};
+
+        let q = match self.reserve() {
+            Ok(q) => q,
+            Err(e) => return Err(e)
+        };
+        if !persist_state(self.to_bytes(), persist) {
+            return Err(PersistFailed);
+        }
+
+        let c = rng().gen_bytes(self.ots.n);
+        Ok(self.sign_leaf(q, msg, c))
+    }
+
+    /// Take the next leaf, so it can't be handed out again.
+    fn reserve(&mut self) -> Result<u32, LmsError> {
+        if self.q as uint >= 1u << self.lms.h {
+            return Err(KeyExhausted);
+        }
+        self.q += 1;
+        Ok(self.q - 1)
+    }
+
+    fn sign_leaf(&self, q: u32, msg: &[u8], c: &[u8]) -> ~[u8] {
+        let mut sig = ~[];
+        sig.push_all(u32str(q));
+        sig.push_all(ots_sign(&self.ots, self.id, q, self.seed, c, msg));
+        sig.push_all(u32str(self.lms.id));
+
+        let mut r = (1u << self.lms.h) + q as uint;
+        for _ in range(0, self.lms.h) {
+            sig.push_all(self.node(r ^ 1));
+            r >>= 1;
+        }
+
+        sig
+    }
+
+    /// Identifier and seed for the child key signed by leaf `q`.
+    fn child_material(&self, q: u32, n: uint) -> (~[u8], ~[u8]) {
+        let id = derive(16, self.id, q, D_CHILD_I, self.seed);
+        let seed = derive(n, self.id, q, D_CHILD_SEED, self.seed);
+
+        (id, seed)
+    }
+
+    fn node<'a>(&'a self, r: uint) -> &'a [u8] {
+        self.tree.slice(r * self.lms.m, (r + 1) * self.lms.m)
+    }
+}
+
+// The seed is the secret; the tree holds public nodes.
+impl Zeroize for LmsPrivateKey {
+    fn zeroize(&mut self) {
+        zeroize_slice(self.seed);
+    }
+}
+
+#[cfg(zeroize)]
+impl Drop for LmsPrivateKey {
+    fn drop(&self) {
+        use std::cast;
+
+        unsafe {
+            cast::transmute_mut(self).zeroize();
+        }
+    }
+}
+
+// Hand `state` to the caller's `persist` and wipe our copy of it.
+fn persist_state(state: ~[u8], persist: &fn(&[u8]) -> bool) -> bool {
+    let mut state = state;
+    let stored = persist(state);
+    zeroize_slice(state);
+    stored
+}
+
+#[deriving(Eq,Clone)]
+pub struct LmsPublicKey {
+    priv lms: LmsParams,
+    priv ots: LmotsParams,
+    priv id: ~[u8],
+    priv root: ~[u8],
+}
+
+impl LmsPublicKey {
+    pub fn from_bytes(bytes: &[u8]) -> Option<LmsPublicKey> {
+        if bytes.len() < 8 {
+            return None;
+        }
+
+        let (lms, ots) = match params(read_u32(bytes.slice(0, 4)), read_u32(bytes.slice(4, 8))) {
+            Some(p) => p,
+            None => return None
+        };
+
+        if bytes.len() != 24 + lms.m {
+            return None;
+        }
+
+        Some(LmsPublicKey {
+            lms: lms,
+            ots: ots,
+            id: bytes.slice(8, 24).to_owned(),
+            root: bytes.slice_from(24).to_owned(),
+        })
+    }
+
+    /// u32str(type) || u32str(otstype) || I || T[1]
+    pub fn to_bytes(&self) -> ~[u8] {
+        let mut out = vec::with_capacity(24 + self.lms.m);
+        out.push_all(u32str(self.lms.id));
+        out.push_all(u32str(self.ots.id));
+        out.push_all(self.id);
+        out.push_all(self.root);
+        out
+    }
+
+    pub fn verify(&self, msg: &[u8], sig: &[u8]) -> bool {
+        let m = self.lms.m;
+        let ots_len = ots_sig_len(&self.ots);
+
+        if sig.len() != 8 + ots_len + self.lms.h * m {
+            return false;
+        }
+        if read_u32(sig.slice(4, 8)) != self.ots.id
+            || read_u32(sig.slice(4 + ots_len, 8 + ots_len)) != self.lms.id {
+            return false;
+        }
+
+        let leaves = 1u << self.lms.h;
+        let q = read_u32(sig.slice(0, 4));
+        if q as uint >= leaves {
+            return false;
+        }
+
+        let kc = ots_candidate(&self.ots, self.id, q, msg, sig.slice(4, 4 + ots_len));
+        let path = sig.slice_from(8 + ots_len);
+
+        let mut r = leaves + q as uint;
+        let mut tmp = node_hash(m, self.id, r, D_LEAF, kc, []);
+        let mut i = 0;
+
+        while r > 1 {
+            let sibling = path.slice(i * m, (i + 1) * m);
+            tmp = if r & 1 == 1 {
+                node_hash(m, self.id, r / 2, D_INTR, sibling, tmp)
+            } else {
+                node_hash(m, self.id, r / 2, D_INTR, tmp, sibling)
+            };
+            r >>= 1;
+            i += 1;
+        }
+
+        tmp == self.root
+    }
+}
+
+/// An HSS private key: a chain of LMS keys in which each level signs the
+/// public key of the level below and the bottom level signs messages.
+pub struct HssPrivateKey {
+    priv levels: ~[LmsPrivateKey],
+    // signed_keys[i] is the signature by levels[i] followed by the public
+    // key of levels[i + 1].
+    priv signed_keys: ~[~[u8]],
+    priv lms_types: ~[u32],
+    priv ots_types: ~[u32],
+}
+
+impl HssPrivateKey {
+    /// Build an HSS key with one level per entry of `lms_types` and
+    /// `ots_types` (between 1 and 8). The top level is derived from `id`
+    /// and `seed`; lower levels are derived from their parent.
+    pub fn new(lms_types: &[u32], ots_types: &[u32],
+               id: &[u8], seed: &[u8]) -> Result<HssPrivateKey, LmsError> {
+        let l = lms_types.len();
+
+        if l == 0 || l > 8 || l != ots_types.len() {
+            return Err(UnsupportedType);
+        }
+
+        let top = match LmsPrivateKey::new(lms_types[0], ots_types[0], id, seed) {
+            Ok(k) => k,
+            Err(e) => return Err(e)
+        };
+
+        let mut key = HssPrivateKey {
+            levels: ~[top],
+            signed_keys: ~[],
+            lms_types: lms_types.to_owned(),
+            ots_types: ots_types.to_owned(),
+        };
+
+        for i in range(1, l) {
+            match key.grow(i) {
+                Ok(()) => (),
+                Err(e) => return Err(e)
+            }
+        }
+
+        Ok(key)
+    }
+
+    pub fn generate(lms_types: &[u32], ots_types: &[u32]) -> Result<HssPrivateKey, LmsError> {
+        use std::rand::{rng, RngUtil};
+
+        if ots_types.len() == 0 {
+            return Err(UnsupportedType);
+        }
+        let n = match lmots_params(ots_types[0]) {
+            Some(ots) => ots.n,
+            None => return Err(UnsupportedType)
+        };
+
+        let mut r = rng();
+        let id = r.gen_bytes(16);
+        let seed = r.gen_bytes(n);
+
+        HssPrivateKey::new(lms_types, ots_types, id, seed)
+    }
+
+    /// Restore a key saved with `to_bytes`. Each level's tree is
+    /// recomputed, and each signed public key is checked against the level
+    /// above and below it.
+    pub fn from_bytes(bytes: &[u8]) -> Result<HssPrivateKey, LmsError> {
+        if bytes.len() < 4 {
+            return Err(BadKeyMaterial);
+        }
+        let l = read_u32(bytes.slice(0, 4)) as uint;
+        if l == 0 || l > 8 {
+            return Err(UnsupportedType);
+        }
+
+        let mut key = HssPrivateKey {
+            levels: ~[],
+            signed_keys: ~[],
+            lms_types: ~[],
+            ots_types: ~[],
+        };
+        let mut off = 4u;
+
+        for _ in range(0, l) {
+            if bytes.len() < off + 8 {
+                return Err(BadKeyMaterial);
+            }
+            let len = match lmots_params(read_u32(bytes.slice(off + 4, off + 8))) {
+                Some(ots) => 28 + ots.n,
+                None => return Err(UnsupportedType)
+            };
+            if bytes.len() < off + len {
+                return Err(BadKeyMaterial);
+            }
+
+            let level = match LmsPrivateKey::from_bytes(bytes.slice(off, off + len)) {
+                Ok(k) => k,
+                Err(e) => return Err(e)
+            };
+            key.lms_types.push(level.lms.id);
+            key.ots_types.push(level.ots.id);
+            key.levels.push(level);
+            off += len;
+        }
+
+        for i in range(1, l) {
+            let sig_len = match lms_sig_len_at(bytes.slice_from(off)) {
+                Some(len) => len,
+                None => return Err(BadKeyMaterial)
+            };
+            let pub_bytes = key.levels[i].public_key().to_bytes();
+            let len = sig_len + pub_bytes.len();
+            if bytes.len() < off + len
+                || bytes.slice(off + sig_len, off + len) != pub_bytes.as_slice()
+                || !key.levels[i - 1].public_key().verify(pub_bytes, bytes.slice(off, off + sig_len)) {
+                return Err(BadKeyMaterial);
+            }
+
+            key.signed_keys.push(bytes.slice(off, off + len).to_owned());
+            off += len;
+        }
+
+        if off != bytes.len() {
+            return Err(BadKeyMaterial);
+        }
+        Ok(key)
+    }
+
+    /// u32str(L) || each level's `LmsPrivateKey::to_bytes`, top first ||
+    /// each signed public key below the top, as it appears in signatures.
+    /// This is secret: it holds the seeds.
+    pub fn to_bytes(&self) -> ~[u8] {
+        let mut out = ~[];
+        out.push_all(u32str(self.levels.len() as u32));
+        for level in self.levels.iter() {
+            let mut bytes = level.to_bytes();
+            out.push_all(bytes);
+            zeroize_slice(bytes);
+        }
+        for signed in self.signed_keys.iter() {
+            out.push_all(signed.as_slice());
+        }
+        out
+    }
+
+    pub fn public_key(&self) -> HssPublicKey {
+        HssPublicKey {
+            levels: self.levels.len() as u32,
+            top: self.levels[0].public_key(),
+        }
+    }
+
+    /// Sign `msg`, as `LmsPrivateKey::sign`: the leaves it uses, and any
+    /// lower-level keys it replaces, are in the state passed to `persist`
+    /// before the signature is computed. A key made by `new` with more than
+    /// one level has already used a leaf of each level above the bottom,
+    /// so store its `to_bytes` before the first `sign`.
+    pub fn sign(&mut self, msg: &[u8], persist: &fn(&[u8]) -> bool) -> Result<~[u8], LmsError> {
+        use std::rand::{rng, RngUtil};
+
+        let l = self.levels.len();
+
+        if self.levels[l - 1].remaining() == 0 {
+            // Replace every exhausted level below the deepest one that can
+            // still certify a new child.
+            let mut j = l - 1;
+            loop {
+                if j == 0 {
+                    return Err(KeyExhausted);
+                }
+                j -= 1;
+                if self.levels[j].remaining() > 0 {
+                    break;
+                }
+            }
+
+            for i in range(j + 1, l) {
+                match self.grow(i) {
+                    Ok(()) => (),
+                    Err(e) => return Err(e)
+                }
+            }
+        }
+
+        let q = match self.levels[l - 1].reserve() {
+            Ok(q) => q,
+            Err(e) => return Err(e)
+        };
+        if !persist_state(self.to_bytes(), persist) {
+            return Err(PersistFailed);
+        }
+
+        let bottom = &self.levels[l - 1];
+        let c = rng().gen_bytes(bottom.ots.n);
+
+        let mut sig = ~[];
+        sig.push_all(u32str((l - 1) as u32));
+        for signed in self.signed_keys.iter() {
+            sig.push_all(signed.as_slice());
+        }
+        sig.push_all(bottom.sign_leaf(q, msg, c));
+
+        Ok(sig)
+    }
+
+    /// Replace `level` (and drop everything below it) with a fresh key
+    /// derived from the next leaf of its parent, signed by that leaf.
+    fn grow(&mut self, level: uint) -> Result<(), LmsError> {
+        use std::rand::{rng, RngUtil};
+
+        self.levels.truncate(level);
+        self.signed_keys.truncate(level - 1);
+
+        let n = match lmots_params(self.ots_types[level]) {
+            Some(ots) => ots.n,
+            None => return Err(UnsupportedType)
+        };
+        let q = match self.levels[level - 1].reserve() {
+            Ok(q) => q,
+            Err(e) => return Err(e)
+        };
+        let (id, seed) = self.levels[level - 1].child_material(q, n);
+
+        let child = match LmsPrivateKey::new(self.lms_types[level], self.ots_types[level],
+                                             id, seed) {
+            Ok(k) => k,
+            Err(e) => return Err(e)
+        };
+        let child_pub = child.public_key().to_bytes();
+
+        let mut signed = {
+            let parent = &self.levels[level - 1];
+            let c = rng().gen_bytes(parent.ots.n);
+            parent.sign_leaf(q, child_pub, c)
+        };
+        signed.push_all(child_pub);
+
+        self.levels.push(child);
+        self.signed_keys.push(signed);
+
+        Ok(())
+    }
+}
+
+// Under `--cfg zeroize` each level wipes itself when dropped; this is
+// for wiping earlier.
+impl Zeroize for HssPrivateKey {
+    fn zeroize(&mut self) {
+        for level in self.levels.mut_iter() {
+            level.zeroize();
+        }
+    }
+}
+
+#[deriving(Eq,Clone)]
+pub struct HssPublicKey {
+    priv levels: u32,
+    priv top: LmsPublicKey,
+}
+
+impl HssPublicKey {
+    pub fn from_bytes(bytes: &[u8]) -> Option<HssPublicKey> {
+        if bytes.len() < 4 {
+            return None;
+        }
+
+        let levels = read_u32(bytes.slice(0, 4));
+        if levels == 0 || levels > 8 {
+            return None;
+        }
+
+        match LmsPublicKey::from_bytes(bytes.slice_from(4)) {
+            Some(top) => Some(HssPublicKey { levels: levels, top: top }),
+            None => None
+        }
+    }
+
+    /// u32str(L) || pub[0]
+    pub fn to_bytes(&self) -> ~[u8] {
+        let mut out = ~[];
+        out.push_all(u32str(self.levels));
+        out.push_all(self.top.to_bytes());
+        out
+    }
+
+    pub fn verify(&self, msg: &[u8], sig: &[u8]) -> bool {
+        if sig.len() < 4 {
+            return false;
+        }
+
+        let nspk = read_u32(sig.slice(0, 4));
+        if nspk + 1 != self.levels {
+            return false;
+        }
+
+        let mut key = self.top.clone();
+        let mut off = 4u;
+
+        for _ in range(0, nspk) {
+            let sig_len = match lms_sig_len_at(sig.slice_from(off)) {
+                Some(len) => len,
+                None => return false
+            };
+            let lms_sig = sig.slice(off, off + sig_len);
+            off += sig_len;
+
+            if sig.len() < off + 4 {
+                return false;
+            }
+            let pub_len = match lms_params(read_u32(sig.slice(off, off + 4))) {
+                Some(lms) => 24 + lms.m,
+                None => return false
+            };
+            if sig.len() < off + pub_len {
+                return false;
+            }
+            let pub_bytes = sig.slice(off, off + pub_len);
+            off += pub_len;
+
+            if !key.verify(pub_bytes, lms_sig) {
+                return false;
+            }
+            key = match LmsPublicKey::from_bytes(pub_bytes) {
+                Some(k) => k,
+                None => return false
+            };
+        }
+
+        key.verify(msg, sig.slice_from(off))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::{lmots_params};
+    use std::vec;
+
+    fn seed(n: uint, base: u8) -> ~[u8] {
+        vec::from_fn(n, |i| base + i as u8)
+    }
+
+    fn from_hex(line: &str) -> ~[u8] {
+        use std::u8;
+        use std::str;
+
+        line.split_iter(' ').collect::<~[&str]>()[2]
+            .iter()
+            .collect::<~[char]>()
+            .chunk_iter(2)
+            .map(|cs| u8::from_str_radix(str::from_chars(cs), 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_lmots_params() {
+        // SP 800-208, Table 2: (type, n, w, p, ls)
+        let table = [
+            (LMOTS_SHAKE_N32_W1, 32u, 1u, 265u, 7u),
+            (LMOTS_SHAKE_N32_W2, 32, 2, 133, 6),
+            (LMOTS_SHAKE_N32_W4, 32, 4, 67, 4),
+            (LMOTS_SHAKE_N32_W8, 32, 8, 34, 0),
+            (LMOTS_SHAKE_N24_W1, 24, 1, 200, 8),
+            (LMOTS_SHAKE_N24_W2, 24, 2, 101, 6),
+            (LMOTS_SHAKE_N24_W4, 24, 4, 51, 4),
+            (LMOTS_SHAKE_N24_W8, 24, 8, 26, 0),
+        ];
+
+        for &(id, n, w, p, ls) in table.iter() {
+            let ots = lmots_params(id).unwrap();
+            assert_eq!((ots.n, ots.w, ots.p, ots.ls), (n, w, p, ls));
+        }
+    }
+
+    #[test]
+    fn test_lms_sign_verify() {
+        let mut sk = LmsPrivateKey::new(LMS_SHAKE_M24_H5, LMOTS_SHAKE_N24_W4,
+                                        seed(16, 0), seed(24, 0x40)).unwrap();
+        let pk = LmsPublicKey::from_bytes(sk.public_key().to_bytes()).unwrap();
+        assert!(pk == sk.public_key());
+
+        let msg = "firmware image".as_bytes();
+        let sig1 = sk.sign(msg, |_| true).unwrap();
+        let sig2 = sk.sign(msg, |_| true).unwrap();
+
+        assert!(pk.verify(msg, sig1));
+        assert!(pk.verify(msg, sig2));
+        assert!(sig1 != sig2);
+        assert!(!pk.verify("firmware imagf".as_bytes(), sig1));
+
+        let mut bad = sig2.clone();
+        bad[3] ^= 1;
+        assert!(!pk.verify(msg, bad));
+
+        let mut bad = sig2.clone();
+        let last = bad.len() - 1;
+        bad[last] ^= 0x80;
+        assert!(!pk.verify(msg, bad));
+
+        assert!(!pk.verify(msg, sig2.slice_to(sig2.len() - 1)));
+    }
+
+    #[test]
+    fn test_lms_exhaustion() {
+        let mut sk = LmsPrivateKey::new(LMS_SHAKE_M24_H5, LMOTS_SHAKE_N24_W8,
+                                        seed(16, 0x10), seed(24, 0x20)).unwrap();
+        let pk = sk.public_key();
+
+        for i in range(0u, 32) {
+            assert_eq!(sk.q() as uint, i);
+            assert_eq!(sk.remaining(), 32 - i);
+            let sig = sk.sign([i as u8], |_| true).unwrap();
+            assert!(pk.verify([i as u8], sig));
+        }
+
+        assert_eq!(sk.remaining(), 0);
+        assert_eq!(sk.sign([0u8], |_| true).unwrap_err(), KeyExhausted);
+    }
+
+    #[test]
+    fn test_lms_persist() {
+        let mut sk = LmsPrivateKey::new(LMS_SHAKE_M24_H5, LMOTS_SHAKE_N24_W8,
+                                        seed(16, 0x30), seed(24, 0x50)).unwrap();
+        let pk = sk.public_key();
+
+        let mut saved = ~[];
+        let sig = sk.sign([1u8], |state| { saved = state.to_owned(); true }).unwrap();
+        assert!(pk.verify([1u8], sig));
+        assert_eq!(saved, sk.to_bytes());
+
+        // A restart picks up at the next leaf.
+        let mut restored = LmsPrivateKey::from_bytes(saved).unwrap();
+        assert_eq!(restored.q(), 1);
+        assert!(restored.public_key() == pk);
+        let sig = restored.sign([2u8], |_| true).unwrap();
+        assert!(pk.verify([2u8], sig));
+        assert_eq!(sig.slice_to(4), [0u8, 0, 0, 1].as_slice());
+
+        // A leaf whose state couldn't be stored is never signed with.
+        assert_eq!(restored.sign([3u8], |_| false).unwrap_err(), PersistFailed);
+        assert_eq!(restored.q(), 3);
+
+        assert_eq!(LmsPrivateKey::from_bytes(saved.slice_to(saved.len() - 1)).unwrap_err(),
+                   BadKeyMaterial);
+        let mut bad = saved.clone();
+        bad[8] = 0xff;
+        assert_eq!(LmsPrivateKey::from_bytes(bad).unwrap_err(), BadKeyMaterial);
+    }
+
+    #[test]
+    fn test_zeroize() {
+        use zeroize::Zeroize;
+
+        let mut sk = HssPrivateKey::new([LMS_SHAKE_M24_H5], [LMOTS_SHAKE_N24_W8],
+                                        seed(16, 0x01), seed(24, 0x02)).unwrap();
+        sk.zeroize();
+        let state = sk.to_bytes();
+        assert!(state.slice_from(state.len() - 24).iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_bad_parameters() {
+        assert!(LmsPrivateKey::new(0x05, LMOTS_SHAKE_N32_W4, seed(16, 0), seed(32, 0)).is_err());
+        assert_eq!(LmsPrivateKey::new(LMS_SHAKE_M32_H5, LMOTS_SHAKE_N24_W4,
+                                      seed(16, 0), seed(24, 0)).unwrap_err(),
+                   UnsupportedType);
+        assert_eq!(LmsPrivateKey::new(LMS_SHAKE_M32_H5, LMOTS_SHAKE_N32_W4,
+                                      seed(15, 0), seed(32, 0)).unwrap_err(),
+                   BadKeyMaterial);
+        assert_eq!(LmsPrivateKey::new(LMS_SHAKE_M24_H20, LMOTS_SHAKE_N24_W4,
+                                      seed(16, 0), seed(24, 0)).unwrap_err(),
+                   TreeTooLarge);
+        assert_eq!(HssPrivateKey::new([LMS_SHAKE_M32_H25], [LMOTS_SHAKE_N32_W8],
+                                      seed(16, 0), seed(32, 0)).unwrap_err(),
+                   TreeTooLarge);
+    }
+
+    #[test]
+    fn test_hss_sign_verify() {
+        let lms_types = [LMS_SHAKE_M24_H5, LMS_SHAKE_M24_H5];
+        let ots_types = [LMOTS_SHAKE_N24_W4, LMOTS_SHAKE_N24_W4];
+        let mut sk = HssPrivateKey::new(lms_types, ots_types,
+                                        seed(16, 0x80), seed(24, 0x90)).unwrap();
+        let pk = HssPublicKey::from_bytes(sk.public_key().to_bytes()).unwrap();
+
+        // 34 signatures run past the first bottom-level tree.
+        for i in range(0u, 34) {
+            let msg = [i as u8, 0xa5];
+            let sig = sk.sign(msg, |_| true).unwrap();
+            assert!(pk.verify(msg, sig));
+            assert!(!pk.verify([i as u8, 0xa4], sig));
+        }
+    }
+
+    #[test]
+    fn test_hss_persist() {
+        let lms_types = [LMS_SHAKE_M24_H5, LMS_SHAKE_M24_H5];
+        let ots_types = [LMOTS_SHAKE_N24_W8, LMOTS_SHAKE_N24_W8];
+        let mut sk = HssPrivateKey::new(lms_types, ots_types,
+                                        seed(16, 0x60), seed(24, 0x70)).unwrap();
+        let pk = sk.public_key();
+
+        let mut saved = sk.to_bytes();
+        for i in range(0u, 31) {
+            sk.sign([i as u8], |state| { saved = state.to_owned(); true }).unwrap();
+        }
+
+        // Restored one signature before the bottom tree runs out, the key
+        // signs its last leaf and then moves on to a new bottom tree.
+        let mut restored = HssPrivateKey::from_bytes(saved).unwrap();
+        assert!(restored.public_key() == pk);
+        for i in range(31u, 34) {
+            let sig = restored.sign([i as u8], |state| { saved = state.to_owned(); true }).unwrap();
+            assert!(pk.verify([i as u8], sig));
+        }
+        assert_eq!(saved, restored.to_bytes());
+
+        // A signed child key that doesn't match the child is refused.
+        let mut bad = saved.clone();
+        let last = bad.len() - 1;
+        bad[last] ^= 1;
+        assert_eq!(HssPrivateKey::from_bytes(bad).unwrap_err(), BadKeyMaterial);
+        assert!(HssPrivateKey::from_bytes(saved.slice_to(saved.len() - 1)).is_err());
+    }
+
+    #[test]
+    fn test_lms_vectors() {
+        use std::io;
+        use std::path::PosixPath;
+
+        let r = match io::file_reader(&PosixPath("test_vectors/LMS_SHAKE_Verify.txt")) {
+            Ok(reader) => reader,
+            Err(msg) => fail!(msg)
+        };
+
+        let mut pk = ~[];
+        let mut msg = ~[];
+        let mut count = 0;
+
+        do r.each_line |line| {
+            if line.starts_with("PK") {
+                pk = from_hex(line);
+            } else if line.starts_with("Msg") {
+                msg = from_hex(line);
+            } else if line.starts_with("Sig") {
+                let sig = from_hex(line);
+                let key = LmsPublicKey::from_bytes(pk).unwrap();
+                assert!(key.verify(msg, sig));
+
+                let mut bad = sig.clone();
+                bad[100] ^= 0x01;
+                assert!(!key.verify(msg, bad));
+
+                count += 1;
+            }
+            true
+        };
+
+        assert_eq!(count, 2);
+    }
+}
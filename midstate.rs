@@ -0,0 +1,299 @@
+// A versioned encoding of a sponge in mid-computation, to stop hashing in
+// one process and continue in another. Integers are little-endian:
+//
+//   offset  size
+//    0       1    MIDSTATE_VERSION
+//    1       1    phase: 0 absorbing, 1 squeezing
+//    2       1    suffix bits the owner appends before padding
+//    3       1    number of suffix bits, below 8
+//    4       2    rate in bits
+//    6       2    capacity in bits
+//    8       4    fixed output length in bits, 0 for any length
+//   12       2    bits in the queue, while absorbing
+//   14       2    bits left to squeeze from the current block
+//   16       2    permutation width in bits
+//   18       1    permutation rounds
+//   19   width/8  the permutation state, in the byte order of `add_bytes`
+//    .   queue/8  the queued bits, rounded up to whole bytes
+//    .   16       SHAKE128 of everything before it
+//
+// The importer passes in the permutation the state was exported from, and
+// its width and round count must match the recorded ones, so a state of
+// Keccak-p[1600, 12] doesn't resume on Keccak-f[1600]. Every other field is
+// checked against it too and the trailing digest against the rest, so a truncated, corrupted or foreign
+// record is refused instead of resumed into a wrong hash. The digest is
+// unkeyed: it catches damage in storage or transit, not a forged record.
+
+use sponge::*;
+use permutation::Permutation;
+use fips202::shake128;
+use std::vec;
+use std::vec::MutableCloneableVector;
+
+pub static MIDSTATE_VERSION: u8 = 2;
+
+static HEADER_LEN: uint = 19;
+static CHECK_LEN: uint = 16;
+
+fn put_le(out: &mut [u8], x: uint) {
+    for i in range(0, out.len()) {
+        out[i] = (x >> (8 * i)) as u8;
+    }
+}
+
+fn get_le(bytes: &[u8]) -> uint {
+    let mut x = 0u;
+    for i in range(0, bytes.len()) {
+        x |= bytes[i] as uint << (8 * i);
+    }
+    x
+}
+
+/// Encode `sponge`, whose owner appends the `suffix_len` low bits of
+/// `suffix` to the message before padding.
+pub fn export<P: Permutation>(sponge: &Sponge<P>, suffix: u8, suffix_len: uint) -> ~[u8] {
+    assert!(suffix_len < 8);
+
+    let state_len = (sponge.rate + sponge.capacity) / 8;
+    let queue_len = (sponge.bits_in_queue + 7) / 8;
+    let body_len = HEADER_LEN + state_len + queue_len;
+    let mut out = vec::from_elem(body_len + CHECK_LEN, 0u8);
+
+    out[0] = MIDSTATE_VERSION;
+    out[1] = if sponge.squeezing { 1 } else { 0 };
+    out[2] = suffix & ((1 << suffix_len) - 1) as u8;
+    out[3] = suffix_len as u8;
+    put_le(out.mut_slice(4, 6), sponge.rate);
+    put_le(out.mut_slice(6, 8), sponge.capacity);
+    put_le(out.mut_slice(8, 12), sponge.fixed_out_len);
+    put_le(out.mut_slice(12, 14), sponge.bits_in_queue);
+    put_le(out.mut_slice(14, 16), sponge.bits_for_squeezing);
+    put_le(out.mut_slice(16, 18), sponge.state.width());
+    out[18] = sponge.state.rounds() as u8;
+
+    sponge.state.extract_bytes(out.mut_slice(HEADER_LEN, HEADER_LEN + state_len), 0);
+    out.mut_slice(HEADER_LEN + state_len, body_len).copy_from(sponge.data_queue.slice_to(queue_len));
+
+    let mut check = [0u8, ..CHECK_LEN];
+    shake128(out.slice_to(body_len), check);
+    out.mut_slice_from(body_len).copy_from(check);
+    out
+}
+
+/// Decode a record made by `export` onto `perm`, the permutation it was
+/// exported from. Returns the sponge, the suffix and the suffix length.
+pub fn import<P: Permutation>(perm: P, record: &[u8]) -> Result<(Sponge<P>, u8, uint), SpongeError> {
+    if record.len() < HEADER_LEN + CHECK_LEN {
+        return Err(InvalidMidstate);
+    }
+
+    let data = record.slice_to(record.len() - CHECK_LEN);
+    let mut check = [0u8, ..CHECK_LEN];
+    shake128(data, check);
+    if check.as_slice() != record.slice_from(data.len()) {
+        return Err(InvalidMidstate);
+    }
+
+    if data[0] != MIDSTATE_VERSION || data[1] > 1 || data[3] >= 8 {
+        return Err(InvalidMidstate);
+    }
+
+    let squeezing = data[1] == 1;
+    let suffix = data[2];
+    let suffix_len = data[3] as uint;
+    let rate = get_le(data.slice(4, 6));
+    let capacity = get_le(data.slice(6, 8));
+    let bits_in_queue = get_le(data.slice(12, 14));
+    let bits_for_squeezing = get_le(data.slice(14, 16));
+
+    if suffix as uint >> suffix_len != 0 {
+        return Err(InvalidMidstate);
+    }
+
+    if get_le(data.slice(16, 18)) != perm.width() || data[18] as uint != perm.rounds() {
+        return Err(InvalidMidstate);
+    }
+
+    let mut sponge = match Sponge::new(perm, rate, capacity) {
+        Ok(sponge) => sponge,
+        Err(_) => return Err(InvalidMidstate)
+    };
+
+    if squeezing {
+        if bits_in_queue != 0 || bits_for_squeezing > rate {
+            return Err(InvalidMidstate);
+        }
+    } else if bits_in_queue >= rate || bits_for_squeezing != 0 {
+        return Err(InvalidMidstate);
+    }
+
+    let state_len = (rate + capacity) / 8;
+    let queue_len = (bits_in_queue + 7) / 8;
+    if data.len() != HEADER_LEN + state_len + queue_len {
+        return Err(InvalidMidstate);
+    }
+
+    // The absorbing code ORs new bits into a partial last byte, so its
+    // unused high bits must be clear.
+    let queue = data.slice_from(HEADER_LEN + state_len);
+    if bits_in_queue % 8 != 0 && queue[queue_len - 1] >> (bits_in_queue % 8) != 0 {
+        return Err(InvalidMidstate);
+    }
+
+    sponge.state.add_bytes(data.slice(HEADER_LEN, HEADER_LEN + state_len), 0);
+    sponge.data_queue.mut_slice_to(queue_len).copy_from(queue);
+    sponge.fixed_out_len = get_le(data.slice(8, 12));
+    sponge.bits_in_queue = bits_in_queue;
+    sponge.squeezing = squeezing;
+    sponge.bits_for_squeezing = bits_for_squeezing;
+
+    Ok((sponge, suffix, suffix_len))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nist::Keccak;
+    use fips202::Shake;
+    use permutation::{KeccakF1600, KeccakP1600, KeccakP800};
+    use sponge::{Sponge, InvalidMidstate};
+    use extra::digest::Digest;
+    use std::vec;
+    use std::vec::MutableCloneableVector;
+
+    #[test]
+    fn test_resume_keccak() {
+        let msg = vec::from_fn(1000, |i| (i * 13) as u8);
+
+        let mut whole = Keccak::new(384);
+        whole.input(msg);
+        let mut expected = [0u8, ..48];
+        whole.result(expected);
+
+        // Stop on a bit boundary in the middle of a block.
+        let mut first = Keccak::new(384);
+        first.input_bits(msg, 4003).unwrap();
+        let saved = first.export_midstate().unwrap();
+
+        let mut second = Keccak::import_midstate(saved).unwrap();
+        second.input_bits([msg[500] >> 3], 5).unwrap();
+        second.input(msg.slice_from(501));
+        let mut res = [0u8, ..48];
+        second.result(res);
+        assert_eq!(res.as_slice(), expected.as_slice());
+
+        // Once the digest is read there is nothing left to resume.
+        assert_eq!(second.export_midstate(), Err(InvalidMidstate));
+    }
+
+    #[test]
+    fn test_resume_shake() {
+        let mut whole = Shake::new(256);
+        whole.input([7u8, ..300]).unwrap();
+        let mut expected = [0u8, ..500];
+        whole.squeeze(expected).unwrap();
+
+        let mut first = Shake::new(256);
+        first.input([7u8, ..300]).unwrap();
+        let mut res = [0u8, ..500];
+        first.squeeze(res.mut_slice_to(150)).unwrap();
+
+        let mut second = Shake::import_midstate(first.export_midstate()).unwrap();
+        second.squeeze(res.mut_slice_from(150)).unwrap();
+        assert_eq!(res.as_slice(), expected.as_slice());
+    }
+
+    #[test]
+    fn test_narrow_sponge() {
+        let mut sp = Sponge::new(KeccakP800::new(22), 552, 248).unwrap();
+        sp.absorb([0x5au8, ..100], 777).unwrap();
+        let saved = export(&sp, 0x2, 2);
+
+        let (mut resumed, suffix, suffix_len) = import(KeccakP800::new(22), saved).unwrap();
+        assert_eq!((suffix, suffix_len), (0x2u8, 2u));
+
+        let mut a = [0u8, ..80];
+        let mut b = [0u8, ..80];
+        sp.squeeze(a, 640).unwrap();
+        resumed.squeeze(b, 640).unwrap();
+        assert_eq!(a.as_slice(), b.as_slice());
+
+        // A record for a 800-bit state doesn't fit Keccak-f[1600].
+        assert!(import(KeccakF1600::new(), saved).is_err());
+    }
+
+    #[test]
+    fn test_rejects_other_rounds() {
+        let mut sp = Sponge::new(KeccakP1600::new(12), 1344, 256).unwrap();
+        sp.absorb([0x5au8, ..100], 800).unwrap();
+        let saved = export(&sp, 0, 0);
+
+        assert!(import(KeccakP1600::new(12), saved).is_ok());
+        assert_eq!(import(KeccakF1600::new(), saved).unwrap_err(), InvalidMidstate);
+        assert_eq!(import(KeccakP1600::new(14), saved).unwrap_err(), InvalidMidstate);
+
+        // Keccak-p[1600, 24] is Keccak-f[1600].
+        let full = Sponge::new(KeccakF1600::new(), 1088, 512).unwrap();
+        assert!(import(KeccakP1600::new(24), export(&full, 0, 0)).is_ok());
+    }
+
+    // `record` with byte `i` set to `b` and the check digest redone, so
+    // only the field checks can catch it.
+    fn with_byte(record: &[u8], i: uint, b: u8) -> ~[u8] {
+        use fips202::shake128;
+
+        let mut bad = record.to_owned();
+        let body_len = bad.len() - 16;
+        bad[i] = b;
+
+        let mut check = [0u8, ..16];
+        shake128(bad.slice_to(body_len), check);
+        bad.mut_slice_from(body_len).copy_from(check);
+        bad
+    }
+
+    #[test]
+    fn test_rejects_bad_records() {
+        let mut kc = Keccak::new(256);
+        kc.input_bits([0xffu8, 0x01], 9).unwrap();
+        let good = kc.export_midstate().unwrap();
+        assert!(Keccak::import_midstate(good).is_ok());
+        assert!(Keccak::import_midstate(with_byte(good, 23, good[23])).is_ok());
+
+        let check = |i: uint, b: u8| {
+            let bad = with_byte(good, i, b);
+            assert_eq!(Keccak::import_midstate(bad).unwrap_err(), InvalidMidstate);
+        };
+
+        let queue_end = good.len() - 17;
+        check(0, 2);                        // version
+        check(1, 2);                        // phase
+        check(2, 1);                        // suffix without suffix bits
+        check(4, 0x41);                     // rate
+        check(12, 0xff);                    // queue length
+        check(16, 0x20);                    // width
+        check(18, 12);                      // rounds
+        check(queue_end, 0xff);             // dirty bits past the queue
+
+        assert!(Keccak::import_midstate(good.slice_to(good.len() - 1)).is_err());
+        assert!(Keccak::import_midstate([]).is_err());
+
+        // Shake and Keccak records aren't interchangeable.
+        assert!(Shake::import_midstate(good).is_err());
+    }
+
+    #[test]
+    fn test_rejects_corrupted_state() {
+        let mut kc = Keccak::new(256);
+        kc.input([0x42u8, ..500]);
+        let good = kc.export_midstate().unwrap();
+
+        // A flipped bit anywhere in the lanes, or in the digest itself,
+        // would otherwise resume into a wrong hash.
+        for &i in [19u, 100, 218, good.len() - 1].iter() {
+            let mut bad = good.clone();
+            bad[i] ^= 0x04;
+            assert_eq!(Keccak::import_midstate(bad).unwrap_err(), InvalidMidstate);
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use consts::*;
+use lanes::load_lane;
+use std::vec;
+
+// Hashing several independent messages at once with Keccak-p[1600]x n:
+// n states are interleaved lane by lane (lane i of instance j is at
+// i * n + j), so every step of the permutation is an inner loop of n
+// identical operations on adjacent words that the compiler can vectorize.
+
+// Keccak[r = 1088, c = 512], the instance behind nist::Keccak::new(256).
+static RATE_BYTES: uint = 136;
+static DIGEST_BYTES: uint = 32;
+
+#[inline(always)]
+fn rol(a: u64, offset: uint) -> u64 {
+    if offset == 0 { a } else { (a << offset) | (a >> (64 - offset)) }
+}
+
+/// Apply Keccak-f[1600] to each of the `n` interleaved states in `state`
+/// (`n` is 2, 4 or 8 and `state.len()` is 25 * n).
+pub fn permute_interleaved(state: &mut [u64], n: uint) {
+    assert!(n == 2 || n == 4 || n == 8);
+    assert_eq!(state.len(), 25 * n);
+
+    let mut c = [0u64, ..40];
+    let mut d = [0u64, ..40];
+    let mut b = [0u64, ..200];
+
+    for round in range(0, ROUND_N) {
+        for x in range(0u, 5) {
+            for j in range(0, n) {
+                c[x * n + j] = state[x * n + j] ^ state[(x + 5) * n + j]
+                    ^ state[(x + 10) * n + j] ^ state[(x + 15) * n + j]
+                    ^ state[(x + 20) * n + j];
+            }
+        }
+
+        for x in range(0u, 5) {
+            for j in range(0, n) {
+                d[x * n + j] = c[((x + 4) % 5) * n + j] ^ rol(c[((x + 1) % 5) * n + j], 1);
+            }
+        }
+
+        for i in range(0u, 25) {
+            let x = i % 5;
+            for j in range(0, n) {
+                b[PI_DEST[i] * n + j] = rol(state[i * n + j] ^ d[x * n + j], RHO_OFFSETS[i]);
+            }
+        }
+
+        for y in range(0u, 5) {
+            for x in range(0u, 5) {
+                let i = x + 5 * y;
+                let i1 = (x + 1) % 5 + 5 * y;
+                let i2 = (x + 2) % 5 + 5 * y;
+                for j in range(0, n) {
+                    state[i * n + j] = b[i * n + j] ^ (!b[i1 * n + j] & b[i2 * n + j]);
+                }
+            }
+        }
+
+        for j in range(0, n) {
+            state[j] ^= ROUND_CONST[round];
+        }
+    }
+}
+
+fn xor_block(state: &mut [u64], n: uint, j: uint, block: &[u8]) {
+    for i in range(0, RATE_BYTES / 8) {
+        state[i * n + j] ^= load_lane(block.slice(8 * i, 8 * i + 8));
+    }
+}
+
+/// Hash up to `n` messages through one interleaved state. A message that
+/// runs out of blocks early has its digest read out right after its last
+/// permutation; its slot just rides along for the remaining ones.
+fn hash_group(group: &[&[u8]], n: uint) -> ~[~[u8]] {
+    let mut state = vec::from_elem(25 * n, 0u64);
+    let mut digests = vec::from_elem(group.len(), ~[]);
+
+    // The final block always exists: it holds the tail and the padding.
+    let blocks = group.iter().map(|m| m.len() / RATE_BYTES + 1).collect::<~[uint]>();
+    let max_blocks = blocks.iter().fold(0u, |a, &b| if b > a { b } else { a });
+
+    for k in range(0, max_blocks) {
+        for (j, msg) in group.iter().enumerate() {
+            if k >= blocks[j] {
+                loop;
+            }
+
+            if k + 1 < blocks[j] {
+                xor_block(state, n, j, msg.slice(k * RATE_BYTES, (k + 1) * RATE_BYTES));
+            } else {
+                let tail = msg.slice_from(k * RATE_BYTES);
+                let mut last = [0u8, ..RATE_BYTES];
+                for (d, s) in last.mut_iter().zip(tail.iter()) {
+                    *d = *s;
+                }
+                last[tail.len()] |= 0x01;
+                last[RATE_BYTES - 1] |= 0x80;
+                xor_block(state, n, j, last);
+            }
+        }
+
+        permute_interleaved(state, n);
+
+        for j in range(0, group.len()) {
+            if blocks[j] == k + 1 {
+                let mut digest = vec::from_elem(DIGEST_BYTES, 0u8);
+                for i in range(0, DIGEST_BYTES) {
+                    digest[i] = (state[(i / 8) * n + j] >> (8 * (i % 8))) as u8;
+                }
+                digests[j] = digest;
+            }
+        }
+    }
+
+    digests
+}
+
+/// Hash each of `inputs` with Keccak-256, `ways` (2, 4 or 8) messages at a
+/// time. The result holds one 32-byte digest per input, in order, equal to
+/// what `nist::Keccak::new(256)` gives for that input alone.
+pub fn keccak256_many(inputs: &[&[u8]], ways: uint) -> ~[~[u8]] {
+    if ways != 2 && ways != 4 && ways != 8 {
+        fail!("ways must be 2, 4 or 8");
+    }
+
+    let mut digests = vec::with_capacity(inputs.len());
+    for group in inputs.chunk_iter(ways) {
+        digests.push_all_move(hash_group(group, ways));
+    }
+
+    digests
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nist::Keccak;
+    use opt64;
+    use extra::digest::Digest;
+    use extra::test::BenchHarness;
+    use std::vec;
+
+    #[test]
+    fn test_permute_interleaved() {
+        use std::rand::{weak_rng, RngUtil};
+
+        let mut rng = weak_rng();
+
+        for &n in [2u, 4, 8].iter() {
+            let mut state = vec::from_fn(25 * n, |_| rng.gen::<u64>());
+            let mut single = vec::from_fn(n, |j| vec::from_fn(25, |i| state[i * n + j]));
+
+            permute_interleaved(state, n);
+
+            for j in range(0, n) {
+                opt64::permute_on_words(single[j]);
+                for i in range(0u, 25) {
+                    assert_eq!(state[i * n + j], single[j][i]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_keccak256_many() {
+        use std::rand::{weak_rng, RngUtil};
+
+        let mut rng = weak_rng();
+
+        // Lengths around the 136-byte block boundary, plus a few longer
+        // messages so the groups run for different numbers of blocks.
+        let mut lengths = ~[0u, 1, 135, 136, 137, 271, 272, 273, 1000, 3];
+        for _ in range(0, 13) {
+            lengths.push(rng.gen::<uint>() % 700);
+        }
+
+        let msgs = lengths.iter().map(|&len| rng.gen_bytes(len)).collect::<~[~[u8]]>();
+        let inputs = msgs.iter().map(|m| m.as_slice()).collect::<~[&[u8]]>();
+
+        let serial = msgs.iter().map(|m| {
+            let mut kc = Keccak::new(256);
+            let mut res = vec::from_elem(32, 0u8);
+            kc.input(*m);
+            kc.result(res);
+            res
+        }).collect::<~[~[u8]]>();
+
+        for &ways in [2u, 4, 8].iter() {
+            assert_eq!(keccak256_many(inputs, ways), serial);
+        }
+    }
+
+    #[bench]
+    fn bench_keccak256_many(bh: &mut BenchHarness) {
+        let msgs = vec::from_fn(64, |i| vec::from_elem(64, i as u8));
+        let inputs = msgs.iter().map(|m| m.as_slice()).collect::<~[&[u8]]>();
+
+        do bh.iter {
+            keccak256_many(inputs, 4);
+        }
+
+        bh.bytes = 64 * 64;
+    }
+}
@@ -1,68 +1,330 @@
 use sponge::*;
+use bitstring::BitString;
+use hasher::{Hasher, KeccakHasher};
+use zeroize::{Zeroize, zeroize_slice};
+use ct::{ct_eq, verify_tag, tag_len_ok};
+use midstate;
+use digest;
+use std::rt::io;
+use permutation::KeccakF1600;
 use extra::digest::Digest;
+use std::vec;
+use std::vec::MutableCloneableVector;
 
 pub struct Keccak {
     priv sponge_state: SpongeState,
     priv hash_size: uint,
+    // The digest of a fixed-size instance once read, so that `result`
+    // keeps returning it.
+    priv digest: Option<~[u8]>,
 }
 
 impl Keccak {
+    /// One of the SHA-3 submission instances: 224, 256, 384 or 512-bit
+    /// output with capacity twice that, or 0 for the default Keccak[c=576]
+    /// with output of any length. Other instances come from
+    /// `KeccakBuilder`.
     pub fn new(hash_size: uint) -> Keccak {
+        match Keccak::try_new(hash_size) {
+            Ok(kc) => kc,
+            Err(_) => fail!("hash_size must be 0, 224, 256, 384, or 512")
+        }
+    }
 
-        let mut sponge = match hash_size {
-            0 => SpongeState::new(1024, 576),
-            224 => SpongeState::new(1152, 448),
-            256 => SpongeState::new(1088, 512),
-            384 => SpongeState::new(832, 768),
-            512 => SpongeState::new(576, 1024),
-            _ => fail!("hash_size must be 0, 224, 256, 384, or 512")
-        };
+    /// Like `new`, but returns `InvalidParameters` for other sizes.
+    pub fn try_new(hash_size: uint) -> Result<Keccak, SpongeError> {
+        match standard_capacity(hash_size) {
+            Some(c) => KeccakBuilder::new().capacity(c).output_bits(hash_size).build(),
+            None => Err(InvalidParameters)
+        }
+    }
+
+    /// The instance `new(hash_size)` makes, with the typestate API.
+    pub fn hasher(hash_size: uint) -> KeccakHasher {
+        match standard_capacity(hash_size) {
+            Some(c) => KeccakBuilder::new().capacity(c).output_bits(hash_size).build_hasher().unwrap(),
+            None => fail!("hash_size must be 0, 224, 256, 384, or 512")
+        }
+    }
 
-        sponge.fixed_out_len = hash_size;
+    pub fn capacity(&self) -> uint {
+        self.sponge_state.capacity
+    }
 
-        Keccak {
-            hash_size: hash_size,
-            sponge_state: sponge,
+    /// Claimed security strength in bits against collisions, and so the
+    /// strength of the instance as a hash function.
+    pub fn security_strength(&self) -> uint {
+        collision_strength(self.sponge_state.capacity, self.hash_size)
+    }
+
+    /// Claimed security strength in bits against (second) preimages.
+    pub fn preimage_strength(&self) -> uint {
+        preimage_strength(self.sponge_state.capacity, self.hash_size)
+    }
+}
+
+fn standard_capacity(hash_size: uint) -> Option<uint> {
+    match hash_size {
+        0 => Some(576),
+        224 => Some(448),
+        256 => Some(512),
+        384 => Some(768),
+        512 => Some(1024),
+        _ => None
+    }
+}
+
+// The claims of the Keccak team for Keccak[c] truncated to n bits: generic
+// attacks on the sponge cost 2^(c/2), so the output length and the capacity
+// both bound the strength. n = 0 stands for output as long as needed.
+fn collision_strength(capacity: uint, output_bits: uint) -> uint {
+    if output_bits == 0 || output_bits / 2 > capacity / 2 {
+        capacity / 2
+    } else {
+        output_bits / 2
+    }
+}
+
+fn preimage_strength(capacity: uint, output_bits: uint) -> uint {
+    if output_bits == 0 || output_bits > capacity / 2 {
+        capacity / 2
+    } else {
+        output_bits
+    }
+}
+
+/// Builds Keccak[r = 1600 - c, c] with any capacity of whole bytes and any
+/// output length, for truncated and non-standard instances, e.g.
+/// `KeccakBuilder::new().capacity(320).output_bits(160).build()`.
+pub struct KeccakBuilder {
+    priv capacity: uint,
+    priv output_bits: uint,
+}
+
+impl KeccakBuilder {
+    /// Starts from the default Keccak[c=576] with arbitrary-length output.
+    pub fn new() -> KeccakBuilder {
+        KeccakBuilder { capacity: 576, output_bits: 0 }
+    }
+
+    /// Capacity in bits: a multiple of 8 between 64 and 1592. Below 64 the
+    /// rate would be more than the sponge's 1536-bit block buffer, and
+    /// `build` returns `RateTooLarge`.
+    pub fn capacity(self, capacity: uint) -> KeccakBuilder {
+        KeccakBuilder { capacity: capacity, .. self }
+    }
+
+    /// Digest length in bits, or 0 for output of any length through
+    /// `Keccak::squeeze`. A digest that ends in a partial byte holds its
+    /// last bits in the least significant end of the last byte.
+    pub fn output_bits(self, output_bits: uint) -> KeccakBuilder {
+        KeccakBuilder { output_bits: output_bits, .. self }
+    }
+
+    pub fn security_strength(&self) -> uint {
+        collision_strength(self.capacity, self.output_bits)
+    }
+
+    pub fn preimage_strength(&self) -> uint {
+        preimage_strength(self.capacity, self.output_bits)
+    }
+
+    /// The hasher, or `InvalidParameters` if the capacity doesn't describe
+    /// an instance, or `RateTooLarge` if it is below 64.
+    pub fn build(&self) -> Result<Keccak, SpongeError> {
+        match self.sponge() {
+            Ok(sponge) => Ok(Keccak {
+                hash_size: self.output_bits,
+                sponge_state: sponge,
+                digest: None,
+            }),
+            Err(e) => Err(e)
         }
     }
+
+    /// Like `build`, with the typestate API.
+    pub fn build_hasher(&self) -> Result<KeccakHasher, SpongeError> {
+        match self.sponge() {
+            Ok(sponge) => Ok(Hasher::new(sponge, 0, 0, self.output_bits)),
+            Err(e) => Err(e)
+        }
+    }
+
+    fn sponge(&self) -> Result<SpongeState, SpongeError> {
+        if self.capacity == 0 || self.capacity >= 1600 {
+            return Err(InvalidParameters);
+        }
+
+        let mut sponge = match Sponge::new(KeccakF1600::new(), 1600 - self.capacity, self.capacity) {
+            Ok(sponge) => sponge,
+            Err(e) => return Err(e)
+        };
+        sponge.fixed_out_len = self.output_bits;
+        Ok(sponge)
+    }
 }
 
-impl Digest for Keccak {
-    fn input(&mut self, input: &[u8]) {
-        let data_bit_len = input.len() * 8;
+impl Keccak {
+    /// Absorb the first `bit_len` bits of `data`, where bit i is bit i % 8
+    /// of byte i / 8. Messages of any bit length can be fed over any number
+    /// of calls, each continuing where the previous one ended.
+    ///
+    /// The NIST KAT files store a trailing partial byte in its most
+    /// significant bits instead; see `BitString` for converting.
+    pub fn input_bits(&mut self, data: &[u8], bit_len: uint) -> Result<(), SpongeError> {
+        self.sponge_state.absorb(data, bit_len)
+    }
 
-        let mut err;
+    pub fn input_bitstring(&mut self, bits: &BitString) -> Result<(), SpongeError> {
+        self.input_bits(bits.as_keccak_bytes(), bits.len())
+    }
 
-        if data_bit_len % 8 == 0 {
-            self.sponge_state.absorb(input, data_bit_len);
-        } else {
-            err = self.sponge_state.absorb(input, data_bit_len - (data_bit_len % 8));
-            if err == Success {
-                let last_byte = input[data_bit_len/8] >> (8 - (data_bit_len % 8));
-                debug!("Got to second absorb");
-                self.sponge_state.absorb(&[last_byte], data_bit_len % 8);
-            } else {
-                fail!(err.to_str());
+    /// Fill `out` with the next `out.len()` bytes of output of an
+    /// arbitrary-length instance, like `Keccak::new(0)`. Can be called
+    /// repeatedly to read a longer stream; `result` is the same as a single
+    /// call.
+    ///
+    /// Fails with `UnalignedBitState` after a `squeeze_bits` call that
+    /// ended mid-byte, and with `OutputLengthMismatch` on fixed-size
+    /// instances.
+    pub fn squeeze(&mut self, out: &mut [u8]) -> Result<(), SpongeError> {
+        if self.sponge_state.is_output_unaligned() {
+            return Err(UnalignedBitState);
+        }
+
+        self.squeeze_bits(out, out.len() * 8)
+    }
+
+    /// Like `squeeze`, for the next `bit_len` bits. A partial last byte
+    /// holds its bits in the least significant end, the rest cleared.
+    pub fn squeeze_bits(&mut self, out: &mut [u8], bit_len: uint) -> Result<(), SpongeError> {
+        if self.hash_size != 0 {
+            return Err(OutputLengthMismatch);
+        }
+
+        self.sponge_state.squeeze(out, bit_len)
+    }
+
+    /// `Digest::result` for fixed-size instances, with `out` required to
+    /// be exactly as long as the digest. Repeated calls return the same
+    /// digest until `reset`. Arbitrary-length instances fill all of `out`,
+    /// continuing the output stream.
+    pub fn try_result(&mut self, out: &mut [u8]) -> Result<(), SpongeError> {
+        if self.hash_size == 0 {
+            return self.squeeze(out);
+        }
+
+        if out.len() != (self.hash_size + 7) / 8 {
+            return Err(OutputLengthMismatch);
+        }
+
+        if self.digest.is_none() {
+            let mut digest = vec::from_elem(out.len(), 0u8);
+            match self.sponge_state.squeeze(digest, self.hash_size) {
+                Ok(()) => self.digest = Some(digest),
+                Err(e) => return Err(e)
             }
         }
+
+        out.copy_from(*self.digest.get_ref());
+        Ok(())
     }
 
-    fn result(&mut self, out: &mut [u8]) {
-        self.sponge_state.squeeze(out, self.sponge_state.fixed_out_len);
+    /// Drop the input so far, and the digest if it was read.
+    pub fn reset(&mut self) {
+        self.sponge_state.reset();
+        self.digest = None;
     }
 
-    fn reset(&mut self) {
-        for x in self.sponge_state.state.mut_iter() {
-            *x = 0u8;
+    /// The state so far, for `import_midstate` to continue from, possibly
+    /// in another process. Fails with `InvalidMidstate` once the digest of
+    /// a fixed-size instance has been read.
+    pub fn export_midstate(&self) -> Result<~[u8], SpongeError> {
+        if self.hash_size != 0 && self.sponge_state.squeezing {
+            return Err(InvalidMidstate);
+        }
+
+        Ok(midstate::export(&self.sponge_state, 0, 0))
+    }
+
+    /// The instance `export_midstate` saved, or `InvalidMidstate` if `data`
+    /// isn't a valid record for Keccak.
+    pub fn import_midstate(data: &[u8]) -> Result<Keccak, SpongeError> {
+        match midstate::import(KeccakF1600::new(), data) {
+            Ok((sponge, 0, 0)) => {
+                if sponge.fixed_out_len != 0 && sponge.squeezing {
+                    return Err(InvalidMidstate);
+                }
+
+                Ok(Keccak {
+                    hash_size: sponge.fixed_out_len,
+                    sponge_state: sponge,
+                    digest: None,
+                })
+            }
+            Ok(_) => Err(InvalidMidstate),
+            Err(e) => Err(e)
+        }
+    }
+
+    /// Whether the digest is `expected`, compared in constant time. For
+    /// arbitrary-length instances, whether the next `expected.len()` bytes
+    /// of output are.
+    pub fn verify(&mut self, expected: &[u8]) -> bool {
+        let min_len = if self.hash_size == 0 {
+            expected.len()
+        } else {
+            (self.hash_size + 7) / 8
+        };
+
+        self.verify_truncated(expected, min_len)
+    }
+
+    /// Like `verify`, also accepting the first bytes of the digest, as long
+    /// as there are at least `min_len` of them. An arbitrary-length
+    /// instance rejects a shorter or empty `expected` before reading any
+    /// output.
+    pub fn verify_truncated(&mut self, expected: &[u8], min_len: uint) -> bool {
+        if self.hash_size == 0 {
+            if !tag_len_ok(expected.len(), min_len) {
+                return false;
+            }
+
+            let mut out = vec::from_elem(expected.len(), 0u8);
+            if self.squeeze(out).is_err() {
+                return false;
+            }
+            return ct_eq(out, expected);
         }
 
-        for x in self.sponge_state.data_queue.mut_iter() {
-            *x = 0u8;
+        let mut digest = vec::from_elem((self.hash_size + 7) / 8, 0u8);
+        if self.try_result(digest).is_err() {
+            return false;
         }
+        verify_tag(digest, expected, min_len)
+    }
+}
+
+// The trait methods can't return errors, so they fail the task on what
+// `input_bits` and `try_result` would report.
+impl Digest for Keccak {
+    fn input(&mut self, input: &[u8]) {
+        match self.input_bits(input, input.len() * 8) {
+            Ok(()) => (),
+            Err(e) => fail!(e.to_str())
+        }
+    }
 
-        self.sponge_state.bits_in_queue = 0;
-        self.sponge_state.squeezing = false;
-        self.sponge_state.bits_for_squeezing = 0;
+    fn result(&mut self, out: &mut [u8]) {
+        match self.try_result(out) {
+            Ok(()) => (),
+            Err(e) => fail!(e.to_str())
+        }
+    }
+
+    fn reset(&mut self) {
+        // The inherent method, which takes precedence over the trait ones.
+        self.reset();
     }
 
     fn output_bits(&self) -> uint {
@@ -70,181 +332,374 @@ impl Digest for Keccak {
     }
 }
 
+impl digest::Update for Keccak {
+    fn update(&mut self, data: &[u8]) {
+        self.input(data);
+    }
+}
+
+impl digest::Reset for Keccak {
+    fn reset(&mut self) {
+        self.reset();
+    }
+}
+
+// 0 for arbitrary-length instances, whose `finalize_into` fills all of
+// `out` as `try_result` does.
+impl digest::OutputSizeUser for Keccak {
+    fn output_size(&self) -> uint {
+        (self.hash_size + 7) / 8
+    }
+}
+
+impl digest::BlockSizeUser for Keccak {
+    fn block_size(&self) -> uint {
+        self.sponge_state.rate / 8
+    }
+}
+
+impl digest::FixedOutput for Keccak {
+    fn finalize_into(self, out: &mut [u8]) {
+        let mut kc = self;
+        kc.result(out);
+    }
+}
+
+impl digest::FixedOutputReset for Keccak {
+    fn finalize_into_reset(&mut self, out: &mut [u8]) {
+        self.result(out);
+        self.reset();
+    }
+}
+
+impl digest::HashMarker for Keccak {}
+
+// So `Keccak` can be handed to code that writes to any `io::Writer`.
+impl io::Writer for Keccak {
+    fn write(&mut self, buf: &[u8]) {
+        self.input(buf);
+    }
+
+    fn flush(&mut self) {}
+}
+
+impl Clone for Keccak {
+    fn clone(&self) -> Keccak {
+        Keccak {
+            sponge_state: self.sponge_state.clone(),
+            hash_size: self.hash_size,
+            digest: self.digest.clone(),
+        }
+    }
+}
+
+impl Zeroize for Keccak {
+    fn zeroize(&mut self) {
+        self.sponge_state.zeroize();
+        match self.digest {
+            Some(ref mut digest) => zeroize_slice(*digest),
+            None => ()
+        }
+        self.digest = None;
+    }
+}
+
+#[cfg(zeroize)]
+impl Drop for Keccak {
+    fn drop(&self) {
+        use std::cast;
+
+        unsafe {
+            cast::transmute_mut(self).zeroize();
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use extra::test::BenchHarness;
+    use bitstring::BitString;
+    use sponge::{InvalidParameters, RateTooLarge, AbsorbAfterSqueeze, UnalignedBitState,
+                 OutputLengthMismatch, BufferTooSmall};
 
-    #[test]
-    fn test_hashing_short() {
+    fn from_hex(hex: &str) -> ~[u8] {
+        use std::str;
+        use std::u8;
+
+        hex.iter()
+            .collect::<~[char]>()
+            .chunk_iter(2)
+            .map(|cs| {
+                let s = str::from_chars(cs);
+                u8::from_str_radix(s, 16).unwrap()
+            })
+            .collect()
+    }
+
+    // Check every vector of a KAT file: "MD" lines for the fixed-size
+    // instances, and 4096-bit "Squeezed" lines for Keccak::new(0).
+    fn check_kat_file(fname: ~str, size: uint) {
         use extra::digest::*;
         use std::io;
         use std::path::PosixPath;
-        use std::u8;
         use std::uint;
         use std::vec;
-        use std::str;
-        use std::cast::transmute;
 
+        let mut len: uint = 0;
+        let mut msg: ~[u8] = ~[];
+
+        let r = match io::file_reader(&PosixPath(fname)) {
+            Ok(reader) => reader,
+            Err(msg) => fail!(msg)
+        };
+
+        do r.each_line |line| {
+            let fields = line.split_iter(' ').collect::<~[&str]>();
+
+            if line.starts_with("Len") {
+                len = uint::from_str(fields[2]).unwrap();
+            } else if line.starts_with("Msg") {
+                msg = from_hex(fields[2]);
+            } else if line.starts_with("MD") || line.starts_with("Squeezed") {
+                let md_ref = from_hex(fields[2]);
+
+                let mut kc = Keccak::new(size);
+                let mut res = vec::from_elem(md_ref.len(), 0u8);
+
+                debug!("Len = %u", len);
+                debug!("Msg = %?", msg);
+                debug!("Reference hash =  %?", md_ref);
+
+                kc.input_bitstring(&BitString::from_nist_bits(msg, len)).unwrap();
+                kc.result(res);
+
+                debug!("Result hash =  %?", res);
+
+                assert!(md_ref == res, fmt!("Error: Reference %? does not match result %?", md_ref, res));
+            }
+            true
+        };
+    }
+
+    #[test]
+    fn test_hashing_short() {
         let sizes = [0u, 224, 256, 384, 512];
 
         for &size in sizes.iter() {
-
             do spawn {
-
-                let mut len: uint = 0;
-                let mut msg: ~[u8] = ~[];
-                let mut md_ref: ~[u8] = ~[];
-
-                let fname = fmt!("test_vectors/ShortMsgKAT_%u.txt", size);
-                let r = match io::file_reader(&PosixPath(fname)) {
-                    Ok(reader) => reader,
-                    Err(msg) => fail!(msg)
-                };
-
-                do r.each_line |line| {
-                    if line.starts_with("Len") {
-                        let s = line.split_iter(' ').collect::<~[&str]>()[2];
-                        len = uint::from_str(s).unwrap();
-                    } else if line.starts_with("Msg") {
-                        let tmp = line.split_iter(' ').collect::<~[&str]>()[2]
-                            .iter()
-                            .collect::<~[char]>();
-
-                        msg =  tmp
-                            .chunk_iter(2)
-                            .map(|cs| {
-                                let s = str::from_chars(cs);
-                                u8::from_str_radix(s, 16).unwrap()
-                            })
-                            .collect();
-                    } else if line.starts_with("MD") && len % 8 == 0 && len != 0 {
-
-                        let tmp = line.split_iter(' ')
-                            .map(|s| s.to_owned())
-                            .collect::<~[~str]>()[2];
-
-
-                        let tmp2 = tmp.iter()
-                            .collect::<~[char]>();
-
-                        md_ref = tmp2
-                            .chunk_iter(2)
-                            .map(|cs| {
-                                let s = str::from_chars(cs);
-                                u8::from_str_radix(s, 16).unwrap()
-                            })
-                            .collect();
-
-                        let mut kc = Keccak::new(size);
-                        let mut res = vec::from_elem(size / 8, 0u8);
-
-                        debug!("Len = %u", md_ref.len());
-                        debug!("Msg = %?",
-                            unsafe { transmute::<&[u8],&[u64]>(msg.as_slice()) });
-                        debug!("Reference hash =  %?",
-                            unsafe { transmute::<&[u8],&[u64]>(md_ref.as_slice()) });
-
-
-                        kc.input(msg);
-                        kc.result(res);
-
-                        debug!("Result hash =  %?",
-                            unsafe { transmute::<&[u8],&[u64]>(res.as_slice()) });
-
-                        assert!(md_ref == res, fmt!("Error: Reference %? does not match result %?", md_ref, res));
-                    }
-                    true
-                };
+                check_kat_file(fmt!("test_vectors/ShortMsgKAT_%u.txt", size), size);
             }
         }
     }
 
     #[test]
     fn test_hashing_long() {
-        use extra::digest::*;
-        use std::io;
-        use std::path::PosixPath;
-        use std::u8;
-        use std::uint;
-        use std::vec;
-        use std::str;
-        use std::cast::transmute;
-
         let sizes = [0u, 224, 256, 384, 512];
 
         for &size in sizes.iter() {
-
             do spawn {
-
-                let mut len: uint = 0;
-                let mut msg: ~[u8] = ~[];
-                let mut md_ref: ~[u8] = ~[];
-
-                let fname = fmt!("test_vectors/LongMsgKAT_%u.txt", size);
-                let r = match io::file_reader(&PosixPath(fname)) {
-                    Ok(reader) => reader,
-                    Err(msg) => fail!(msg)
-                };
-
-                do r.each_line |line| {
-                    if line.starts_with("Len") {
-                        let s = line.split_iter(' ').collect::<~[&str]>()[2];
-                        len = uint::from_str(s).unwrap();
-                    } else if line.starts_with("Msg") {
-                        let tmp = line.split_iter(' ').collect::<~[&str]>()[2]
-                            .iter()
-                            .collect::<~[char]>();
-
-                        msg =  tmp
-                            .chunk_iter(2)
-                            .map(|cs| {
-                                let s = str::from_chars(cs);
-                                u8::from_str_radix(s, 16).unwrap()
-                            })
-                            .collect();
-                    } else if line.starts_with("MD") && len % 8 == 0 && len != 0 {
-
-                        let tmp = line.split_iter(' ')
-                            .map(|s| s.to_owned())
-                            .collect::<~[~str]>()[2];
-
-
-                        let tmp2 = tmp.iter()
-                            .collect::<~[char]>();
-
-                        md_ref = tmp2
-                            .chunk_iter(2)
-                            .map(|cs| {
-                                let s = str::from_chars(cs);
-                                u8::from_str_radix(s, 16).unwrap()
-                            })
-                            .collect();
-
-                        let mut kc = Keccak::new(size);
-                        let mut res = vec::from_elem(size / 8, 0u8);
-
-                        debug!("Len = %u", md_ref.len());
-                        debug!("Msg = %?",
-                            unsafe { transmute::<&[u8],&[u64]>(msg.as_slice()) });
-                        debug!("Reference hash =  %?",
-                            unsafe { transmute::<&[u8],&[u64]>(md_ref.as_slice()) });
-
-
-                        kc.input(msg);
-                        kc.result(res);
-
-                        debug!("Result hash =  %?",
-                            unsafe { transmute::<&[u8],&[u64]>(res.as_slice()) });
-
-                        assert!(md_ref == res, fmt!("Error: Reference %? does not match result %?", md_ref, res));
-                    }
-                    true
-                };
+                check_kat_file(fmt!("test_vectors/LongMsgKAT_%u.txt", size), size);
             }
         }
     }
 
+    #[test]
+    fn test_incremental_squeeze() {
+        use std::vec;
+
+        let msg = vec::from_elem(300, 0x5cu8);
+
+        let mut once = vec::from_elem(700, 0u8);
+        let mut kc = Keccak::new(0);
+        kc.input(msg);
+        kc.result(once);
+
+        let mut pieces = vec::from_elem(700, 0u8);
+        let mut kc = Keccak::new(0);
+        kc.input(msg);
+        kc.squeeze(pieces.mut_slice(0, 1)).unwrap();
+        kc.squeeze(pieces.mut_slice(1, 128)).unwrap();
+        kc.squeeze(pieces.mut_slice(128, 700)).unwrap();
+
+        assert_eq!(once, pieces);
+    }
+
+    #[test]
+    fn test_builder() {
+        use extra::digest::Digest;
+        use std::vec;
+
+        let msg = vec::from_fn(200, |i| i as u8);
+
+        let hash = |kc: &mut Keccak, len: uint| -> ~[u8] {
+            let mut res = vec::from_elem(len, 0u8);
+            kc.input(msg);
+            kc.result(res);
+            res
+        };
+
+        let mut std256 = Keccak::new(256);
+        let mut built = KeccakBuilder::new().capacity(512).output_bits(256).build().unwrap();
+        assert_eq!(hash(&mut built, 32), hash(&mut std256, 32));
+
+        // A truncated instance gives a prefix of the arbitrary-length output
+        // with the same capacity.
+        let mut long = KeccakBuilder::new().capacity(320).build().unwrap();
+        let mut short = KeccakBuilder::new().capacity(320).output_bits(160).build().unwrap();
+        let stream = hash(&mut long, 64);
+        assert_eq!(hash(&mut short, 20), stream.slice_to(20).to_owned());
+
+        assert_eq!(KeccakBuilder::new().capacity(0).build().unwrap_err(), InvalidParameters);
+        assert_eq!(KeccakBuilder::new().capacity(1600).build().unwrap_err(), InvalidParameters);
+        assert_eq!(KeccakBuilder::new().capacity(300).build().unwrap_err(), InvalidParameters);
+        assert_eq!(KeccakBuilder::new().capacity(8).build().unwrap_err(), RateTooLarge);
+        assert_eq!(KeccakBuilder::new().capacity(56).build().unwrap_err(), RateTooLarge);
+        assert!(KeccakBuilder::new().capacity(64).build().is_ok());
+        assert!(KeccakBuilder::new().capacity(1592).build().is_ok());
+
+        // Output bits beyond a partial last byte are cleared.
+        let mut odd = KeccakBuilder::new().capacity(320).output_bits(12).build().unwrap();
+        let res = hash(&mut odd, 2);
+        assert_eq!(res[0], stream[0]);
+        assert_eq!(res[1], stream[1] & 0x0F);
+    }
+
+    #[test]
+    fn test_result_twice() {
+        let mut kc = Keccak::new(224);
+        kc.input([0x61u8, 0x62, 0x63]);
+        let mut first = [0u8, ..28];
+        let mut second = [0u8, ..28];
+        kc.result(first);
+        kc.result(second);
+        assert_eq!(first.as_slice(), second.as_slice());
+
+        kc.reset();
+        kc.input([0x61u8, 0x62, 0x63]);
+        kc.result(second);
+        assert_eq!(first.as_slice(), second.as_slice());
+    }
+
+    #[test]
+    fn test_writer() {
+        use std::rt::io::Writer;
+        use std::vec;
+
+        let msg = vec::from_fn(500, |i| (i * i) as u8);
+        let mut kc = Keccak::new(224);
+        kc.input(msg);
+        let mut expected = [0u8, ..28];
+        kc.result(expected);
+
+        let mut kc = Keccak::new(224);
+        for chunk in msg.chunk_iter(144) {
+            kc.write(chunk);
+        }
+        assert!(kc.verify(expected));
+    }
+
+    #[test]
+    fn test_clone() {
+        use std::vec;
+
+        // The prefix ends mid-byte, so the copies share a partial queue.
+        let prefix = vec::from_fn(250, |i| (7 * i) as u8);
+        let mut kc = Keccak::new(512);
+        kc.input_bits(prefix, 1995).unwrap();
+
+        for suffix in [~[], ~[0x01u8], vec::from_elem(300, 0xeeu8)].iter() {
+            let mut forked = kc.clone();
+            forked.input_bits(*suffix, suffix.len() * 8).unwrap();
+            let mut res = [0u8, ..64];
+            forked.result(res);
+
+            let mut msg = BitString::from_keccak_bits(prefix, 1995);
+            msg.append(&BitString::from_bytes(*suffix));
+            let mut fresh = Keccak::new(512);
+            fresh.input_bitstring(&msg).unwrap();
+            let mut expected = [0u8, ..64];
+            fresh.result(expected);
+
+            assert_eq!(res.as_slice(), expected.as_slice());
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        let mut kc = Keccak::new(256);
+        kc.input([0x61u8, 0x62, 0x63]);
+        let mut digest = [0u8, ..32];
+        kc.result(digest);
+
+        assert!(kc.verify(digest));
+        assert!(!kc.verify(digest.slice_to(16)));
+        assert!(kc.verify_truncated(digest.slice_to(16), 16));
+        assert!(!kc.verify_truncated(digest.slice_to(15), 16));
+
+        digest[31] ^= 1;
+        assert!(!kc.verify(digest));
+
+        let mut kc = Keccak::new(0);
+        let mut out = [0u8, ..40];
+        kc.squeeze(out).unwrap();
+        let mut kc = Keccak::new(0);
+        assert!(!kc.verify([]));
+        assert!(!kc.verify_truncated(out.slice_to(8), 16));
+        assert!(kc.verify(out.slice_to(20)));
+        assert!(kc.verify(out.slice_from(20)));
+    }
+
+    #[test]
+    fn test_zeroize() {
+        use zeroize::Zeroize;
+
+        let mut kc = Keccak::new(256);
+        kc.input([0x61u8, 0x62, 0x63]);
+        let mut first = [0u8, ..32];
+        kc.result(first);
+
+        kc.zeroize();
+        kc.input([0x61u8, 0x62, 0x63]);
+        let mut second = [0u8, ..32];
+        kc.result(second);
+        assert_eq!(first.as_slice(), second.as_slice());
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(Keccak::try_new(160).unwrap_err(), InvalidParameters);
+
+        let mut kc = Keccak::new(256);
+        assert_eq!(kc.input_bits([1u8, 2], 17), Err(BufferTooSmall));
+        assert_eq!(kc.try_result([0u8, ..64]), Err(OutputLengthMismatch));
+        assert_eq!(kc.squeeze([0u8, ..32]), Err(OutputLengthMismatch));
+        assert!(kc.try_result([0u8, ..32]).is_ok());
+        assert_eq!(kc.input_bits([1u8, 2], 16), Err(AbsorbAfterSqueeze));
+
+        let mut kc = Keccak::new(0);
+        assert!(kc.squeeze_bits([0u8, ..1], 5).is_ok());
+        assert_eq!(kc.squeeze([0u8, ..8]), Err(UnalignedBitState));
+        assert!(kc.squeeze_bits([0u8, ..1], 3).is_ok());
+        assert!(kc.squeeze([0u8, ..8]).is_ok());
+    }
+
+    #[test]
+    fn test_security_strength() {
+        assert_eq!(Keccak::new(256).security_strength(), 128);
+        assert_eq!(Keccak::new(256).preimage_strength(), 256);
+        assert_eq!(Keccak::new(0).security_strength(), 288);
+
+        let b = KeccakBuilder::new().capacity(320).output_bits(160);
+        assert_eq!(b.security_strength(), 80);
+        assert_eq!(b.preimage_strength(), 160);
+
+        let b = KeccakBuilder::new().capacity(256).output_bits(512);
+        assert_eq!(b.security_strength(), 128);
+        assert_eq!(b.preimage_strength(), 128);
+        assert_eq!(b.build().unwrap().capacity(), 256);
+    }
+
     #[bench]
     fn bench_throughput(bh: &mut BenchHarness) {
         use std::rand::{weak_rng, RngUtil};
@@ -0,0 +1,211 @@
+use consts::*;
+use lanes::{load_lanes, store_lanes};
+
+// Keccak-f[1600] with all 24 rounds unrolled, theta and chi computed per
+// plane with rho and pi folded into the lane addressing, and the "lane
+// complementing" transform: lanes 1, 2, 8, 12, 17 and 20 are kept inverted
+// inside the permutation, which turns all but 8 of the 25 NOTs of chi per
+// round into plain AND/OR. `reference` is the readable version of the same
+// function and is used to check this one.
+
+static COMPLEMENTED: [uint, ..6] = [1, 2, 8, 12, 17, 20];
+
+#[inline(always)]
+fn rol(a: u64, offset: uint) -> u64 {
+    (a << offset) | (a >> (64 - offset))
+}
+
+// One round from lanes $A into lanes $E.
+macro_rules! round(
+    ($A:ident, $E:ident, $rc:expr) => ({
+        let c0 = $A[0] ^ $A[5] ^ $A[10] ^ $A[15] ^ $A[20];
+        let c1 = $A[1] ^ $A[6] ^ $A[11] ^ $A[16] ^ $A[21];
+        let c2 = $A[2] ^ $A[7] ^ $A[12] ^ $A[17] ^ $A[22];
+        let c3 = $A[3] ^ $A[8] ^ $A[13] ^ $A[18] ^ $A[23];
+        let c4 = $A[4] ^ $A[9] ^ $A[14] ^ $A[19] ^ $A[24];
+        let d0 = c4 ^ rol(c1, 1);
+        let d1 = c0 ^ rol(c2, 1);
+        let d2 = c1 ^ rol(c3, 1);
+        let d3 = c2 ^ rol(c4, 1);
+        let d4 = c3 ^ rol(c0, 1);
+
+        {
+            let b0 = $A[0] ^ d0;
+            let b1 = rol($A[6] ^ d1, 44);
+            let b2 = rol($A[12] ^ d2, 43);
+            let b3 = rol($A[18] ^ d3, 21);
+            let b4 = rol($A[24] ^ d4, 14);
+            $E[0] = b0 ^ (b1 | b2) ^ $rc;
+            $E[1] = b1 ^ (!b2 | b3);
+            $E[2] = b2 ^ (b3 & b4);
+            $E[3] = b3 ^ (b4 | b0);
+            $E[4] = b4 ^ (b0 & b1);
+        }
+
+        {
+            let b0 = rol($A[3] ^ d3, 28);
+            let b1 = rol($A[9] ^ d4, 20);
+            let b2 = rol($A[10] ^ d0, 3);
+            let b3 = rol($A[16] ^ d1, 45);
+            let b4 = rol($A[22] ^ d2, 61);
+            $E[5] = b0 ^ (b1 | b2);
+            $E[6] = b1 ^ (b2 & b3);
+            $E[7] = b2 ^ (b3 | !b4);
+            $E[8] = b3 ^ (b4 | b0);
+            $E[9] = b4 ^ (b0 & b1);
+        }
+
+        {
+            let b0 = rol($A[1] ^ d1, 1);
+            let b1 = rol($A[7] ^ d2, 6);
+            let b2 = rol($A[13] ^ d3, 25);
+            let b3 = rol($A[19] ^ d4, 8);
+            let b4 = rol($A[20] ^ d0, 18);
+            $E[10] = b0 ^ (b1 | b2);
+            $E[11] = b1 ^ (b2 & b3);
+            $E[12] = b2 ^ (!b3 & b4);
+            $E[13] = b3 ^ !(b4 | b0);
+            $E[14] = b4 ^ (b0 & b1);
+        }
+
+        {
+            let b0 = rol($A[4] ^ d4, 27);
+            let b1 = rol($A[5] ^ d0, 36);
+            let b2 = rol($A[11] ^ d1, 10);
+            let b3 = rol($A[17] ^ d2, 15);
+            let b4 = rol($A[23] ^ d3, 56);
+            $E[15] = b0 ^ (b1 & b2);
+            $E[16] = b1 ^ (b2 | b3);
+            $E[17] = b2 ^ (!b3 | b4);
+            $E[18] = b3 ^ !(b4 & b0);
+            $E[19] = b4 ^ (b0 | b1);
+        }
+
+        {
+            let b0 = rol($A[2] ^ d2, 62);
+            let b1 = rol($A[8] ^ d3, 55);
+            let b2 = rol($A[14] ^ d4, 39);
+            let b3 = rol($A[15] ^ d0, 41);
+            let b4 = rol($A[21] ^ d1, 2);
+            $E[20] = b0 ^ (!b1 & b2);
+            $E[21] = b1 ^ !(b2 | b3);
+            $E[22] = b2 ^ (b3 & b4);
+            $E[23] = b3 ^ (b4 | b0);
+            $E[24] = b4 ^ (b0 & b1);
+        }
+    })
+)
+
+pub fn permute(state: &mut[u8]) {
+    let mut lanes = [0u64, ..25];
+    load_lanes(state, lanes);
+    permute_on_words(lanes);
+    store_lanes(lanes, state);
+}
+
+pub fn permute_on_words(state: &mut [u64]) {
+    let mut a = [0u64, ..25];
+    let mut e = [0u64, ..25];
+
+    for i in range(0, 25) {
+        a[i] = state[i];
+    }
+    for &i in COMPLEMENTED.iter() {
+        a[i] = !a[i];
+    }
+
+    round!(a, e, ROUND_CONST[0]);
+    round!(e, a, ROUND_CONST[1]);
+    round!(a, e, ROUND_CONST[2]);
+    round!(e, a, ROUND_CONST[3]);
+    round!(a, e, ROUND_CONST[4]);
+    round!(e, a, ROUND_CONST[5]);
+    round!(a, e, ROUND_CONST[6]);
+    round!(e, a, ROUND_CONST[7]);
+    round!(a, e, ROUND_CONST[8]);
+    round!(e, a, ROUND_CONST[9]);
+    round!(a, e, ROUND_CONST[10]);
+    round!(e, a, ROUND_CONST[11]);
+    round!(a, e, ROUND_CONST[12]);
+    round!(e, a, ROUND_CONST[13]);
+    round!(a, e, ROUND_CONST[14]);
+    round!(e, a, ROUND_CONST[15]);
+    round!(a, e, ROUND_CONST[16]);
+    round!(e, a, ROUND_CONST[17]);
+    round!(a, e, ROUND_CONST[18]);
+    round!(e, a, ROUND_CONST[19]);
+    round!(a, e, ROUND_CONST[20]);
+    round!(e, a, ROUND_CONST[21]);
+    round!(a, e, ROUND_CONST[22]);
+    round!(e, a, ROUND_CONST[23]);
+
+    for &i in COMPLEMENTED.iter() {
+        a[i] = !a[i];
+    }
+    for i in range(0, 25) {
+        state[i] = a[i];
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use reference;
+    use extra::test::BenchHarness;
+
+    #[test]
+    fn test_against_reference() {
+        use std::rand::{weak_rng, RngUtil};
+
+        let mut rng = weak_rng();
+
+        for _ in range(0, 100) {
+            let mut fast = [0u64, ..25];
+            for i in range(0, 25) {
+                fast[i] = rng.gen();
+            }
+            let mut slow = fast;
+
+            permute_on_words(fast);
+            reference::permute_on_words(slow);
+
+            assert_eq!(fast.as_slice(), slow.as_slice());
+        }
+    }
+
+    #[test]
+    fn test_permute_bytes() {
+        use std::rand::{weak_rng, RngUtil};
+
+        let mut rng = weak_rng();
+        let mut fast = rng.gen_bytes(200);
+        let mut slow = fast.clone();
+
+        permute(fast);
+        reference::permute(slow);
+
+        assert_eq!(fast, slow);
+    }
+
+    #[bench]
+    fn bench_permute(bh: &mut BenchHarness) {
+        let mut state = [0u64, ..25];
+
+        do bh.iter {
+            permute_on_words(state);
+        }
+
+        bh.bytes = 200;
+    }
+
+    #[bench]
+    fn bench_permute_reference(bh: &mut BenchHarness) {
+        let mut state = [0u64, ..25];
+
+        do bh.iter {
+            reference::permute_on_words(state);
+        }
+
+        bh.bytes = 200;
+    }
+}
@@ -0,0 +1,329 @@
+// Fixed-size digest values, one type per algorithm and length, so callers
+// don't size output buffers by hand. They print as hex, parse from hex or
+// base64 and compare in constant time. Built with `--cfg serialize`, they
+// also serialize as hex strings to JSON, which people read, and as a
+// sequence of raw bytes to EBML.
+
+use nist::Keccak;
+use fips202::Shake;
+use ct::ct_eq;
+use extra::base64::{ToBase64, FromBase64, STANDARD};
+use extra::digest::Digest;
+use digest::XofReader;
+use std::from_str::FromStr;
+use std::str;
+use std::u8;
+
+fn to_hex(bytes: &[u8], upper: bool) -> ~str {
+    let mut s = ~"";
+    for &b in bytes.iter() {
+        s.push_str(if upper { fmt!("%02X", b as uint) } else { fmt!("%02x", b as uint) });
+    }
+    s
+}
+
+// Decode hex of either case, or base64, to exactly `out.len()` bytes.
+fn parse(s: &str, out: &mut [u8]) -> bool {
+    if s.len() == 2 * out.len() {
+        let chars = s.iter().collect::<~[char]>();
+        for (i, cs) in chars.chunk_iter(2).enumerate() {
+            match u8::from_str_radix(str::from_chars(cs), 16) {
+                Some(b) => out[i] = b,
+                None => return false
+            }
+        }
+        return true;
+    }
+
+    match s.from_base64() {
+        Ok(bytes) => {
+            if bytes.len() != out.len() {
+                return false;
+            }
+            for i in range(0, out.len()) {
+                out[i] = bytes[i];
+            }
+            true
+        }
+        Err(_) => false
+    }
+}
+
+macro_rules! digest_output(
+    ($name:ident, $len:expr, $hash:ident($arg:expr)) => (
+        pub struct $name {
+            priv bytes: [u8, ..$len],
+        }
+
+        impl $name {
+            pub fn from_bytes(bytes: &[u8]) -> Option<$name> {
+                if bytes.len() != $len {
+                    return None;
+                }
+
+                let mut out = $name { bytes: [0u8, ..$len] };
+                for i in range(0, $len) {
+                    out.bytes[i] = bytes[i];
+                }
+                Some(out)
+            }
+
+            /// The digest `d` computes, or `None` if it has a different
+            /// fixed length. Arbitrary-length hashes give their next
+            /// bytes of output.
+            pub fn from_digest<D: Digest>(d: &mut D) -> Option<$name> {
+                let bits = d.output_bits();
+                if bits != 0 && bits != 8 * $len {
+                    return None;
+                }
+
+                let mut out = $name { bytes: [0u8, ..$len] };
+                d.result(out.bytes);
+                Some(out)
+            }
+
+            /// The next bytes `r` reads, as from an extendable-output
+            /// function.
+            pub fn from_reader<R: XofReader>(r: &mut R) -> $name {
+                let mut out = $name { bytes: [0u8, ..$len] };
+                r.read(out.bytes);
+                out
+            }
+
+            pub fn digest(msg: &[u8]) -> $name {
+                let mut out = $name { bytes: [0u8, ..$len] };
+                $hash($arg, msg, out.bytes);
+                out
+            }
+
+            pub fn as_bytes<'a>(&'a self) -> &'a [u8] {
+                self.bytes.as_slice()
+            }
+
+            pub fn to_hex(&self) -> ~str {
+                to_hex(self.bytes, false)
+            }
+
+            pub fn to_upper_hex(&self) -> ~str {
+                to_hex(self.bytes, true)
+            }
+
+            pub fn to_base64(&self) -> ~str {
+                self.bytes.as_slice().to_base64(STANDARD)
+            }
+        }
+
+        impl Clone for $name {
+            fn clone(&self) -> $name {
+                $name { bytes: self.bytes }
+            }
+        }
+
+        impl Eq for $name {
+            fn eq(&self, other: &$name) -> bool {
+                ct_eq(self.bytes, other.bytes)
+            }
+
+            fn ne(&self, other: &$name) -> bool {
+                !self.eq(other)
+            }
+        }
+
+        impl ToStr for $name {
+            fn to_str(&self) -> ~str {
+                self.to_hex()
+            }
+        }
+
+        impl FromStr for $name {
+            fn from_str(s: &str) -> Option<$name> {
+                let mut out = $name { bytes: [0u8, ..$len] };
+                if parse(s, out.bytes) { Some(out) } else { None }
+            }
+        }
+    )
+)
+
+fn keccak(hash_size: uint, msg: &[u8], out: &mut [u8]) {
+    let mut kc = Keccak::new(hash_size);
+    kc.input(msg);
+    kc.result(out);
+}
+
+fn shake(security: uint, msg: &[u8], out: &mut [u8]) {
+    let mut sh = Shake::new(security);
+    sh.input(msg).unwrap();
+    sh.squeeze(out).unwrap();
+}
+
+digest_output!(Keccak224Output, 28, keccak(224))
+digest_output!(Keccak256Output, 32, keccak(256))
+digest_output!(Keccak384Output, 48, keccak(384))
+digest_output!(Keccak512Output, 64, keccak(512))
+digest_output!(Shake128Output, 32, shake(128))
+digest_output!(Shake256Output, 64, shake(256))
+
+macro_rules! digest_serialize(
+    ($name:ident, $len:expr) => (
+        impl Encodable<json::Encoder> for $name {
+            fn encode(&self, s: &mut json::Encoder) {
+                s.emit_str(self.to_hex());
+            }
+        }
+
+        impl Encodable<json::PrettyEncoder> for $name {
+            fn encode(&self, s: &mut json::PrettyEncoder) {
+                s.emit_str(self.to_hex());
+            }
+        }
+
+        impl Decodable<json::Decoder> for $name {
+            fn decode(d: &mut json::Decoder) -> $name {
+                let s = d.read_str();
+                match FromStr::from_str(s.as_slice()) {
+                    Some(out) => out,
+                    None => fail!("invalid digest: %s", s)
+                }
+            }
+        }
+
+        impl Encodable<ebml::writer::Encoder> for $name {
+            fn encode(&self, s: &mut ebml::writer::Encoder) {
+                do s.emit_seq($len) |s| {
+                    for i in range(0u, $len) {
+                        s.emit_seq_elt(i, |s| s.emit_u8(self.as_bytes()[i]));
+                    }
+                }
+            }
+        }
+
+        impl Decodable<ebml::reader::Decoder> for $name {
+            fn decode(d: &mut ebml::reader::Decoder) -> $name {
+                do d.read_seq |d, len| {
+                    if len != $len {
+                        fail!("invalid digest length: %u", len);
+                    }
+
+                    let mut bytes = [0u8, ..$len];
+                    for i in range(0u, $len) {
+                        bytes[i] = d.read_seq_elt(i, |d| d.read_u8());
+                    }
+                    $name::from_bytes(bytes).unwrap()
+                }
+            }
+        }
+    )
+)
+
+#[cfg(serialize)]
+mod serialize {
+    use super::*;
+    use extra::serialize::{Encoder, Encodable, Decoder, Decodable};
+    use extra::{json, ebml};
+    use std::from_str::FromStr;
+
+    digest_serialize!(Keccak224Output, 28)
+    digest_serialize!(Keccak256Output, 32)
+    digest_serialize!(Keccak384Output, 48)
+    digest_serialize!(Keccak512Output, 64)
+    digest_serialize!(Shake128Output, 32)
+    digest_serialize!(Shake256Output, 64)
+
+    #[cfg(test)]
+    mod test {
+        use super::super::*;
+        use extra::{json, ebml};
+        use extra::serialize::{Decoder, Encodable, Decodable};
+        use std::io;
+        use std::vec;
+
+        #[test]
+        fn test_serialize() {
+            let d = Keccak384Output::digest([1u8, 2, 3]);
+
+            let s = do io::with_str_writer |wr| {
+                let mut encoder = json::Encoder(wr);
+                d.encode(&mut encoder);
+            };
+            assert_eq!(s, fmt!("\"%s\"", d.to_hex()));
+
+            let pretty = do io::with_str_writer |wr| {
+                let mut encoder = json::PrettyEncoder(wr);
+                d.encode(&mut encoder);
+            };
+            assert_eq!(pretty, s);
+
+            let mut decoder = json::Decoder(json::from_str(s).unwrap());
+            let back: Keccak384Output = Decodable::decode(&mut decoder);
+            assert_eq!(back, d);
+        }
+
+        #[test]
+        fn test_serialize_binary() {
+            let d = Shake256Output::digest([1u8, 2, 3]);
+
+            let bytes = do io::with_bytes_writer |wr| {
+                let mut encoder = ebml::writer::Encoder(wr);
+                d.encode(&mut encoder);
+            };
+
+            let mut decoder = ebml::reader::Decoder(ebml::reader::Doc(@bytes));
+            let elts = do decoder.read_seq |d, len| {
+                vec::from_fn(len, |i| d.read_seq_elt(i, |d| d.read_u8()))
+            };
+            assert_eq!(elts.as_slice(), d.as_bytes());
+
+            let mut decoder = ebml::reader::Decoder(ebml::reader::Doc(@bytes));
+            let back: Shake256Output = Decodable::decode(&mut decoder);
+            assert_eq!(back, d);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nist::Keccak;
+    use fips202::Shake;
+    use extra::digest::Digest;
+    use std::from_str::from_str;
+
+    static KECCAK256_EMPTY: &'static str =
+        "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470";
+
+    #[test]
+    fn test_formats() {
+        let d = Keccak256Output::digest([]);
+        assert_eq!(d.to_hex(), KECCAK256_EMPTY.to_owned());
+        assert_eq!(d.to_str(), KECCAK256_EMPTY.to_owned());
+        assert_eq!(d.to_upper_hex(),
+                   ~"C5D2460186F7233C927E7DB2DCC703C0E500B653CA82273B7BFAD8045D85A470");
+
+        assert_eq!(from_str::<Keccak256Output>(KECCAK256_EMPTY), Some(d.clone()));
+        assert_eq!(from_str::<Keccak256Output>(d.to_upper_hex().as_slice()), Some(d.clone()));
+        assert_eq!(from_str::<Keccak256Output>(d.to_base64().as_slice()), Some(d.clone()));
+
+        assert!(from_str::<Keccak256Output>(KECCAK256_EMPTY.slice_from(2)).is_none());
+        assert!(from_str::<Keccak224Output>(KECCAK256_EMPTY).is_none());
+        assert!(from_str::<Keccak256Output>("zz").is_none());
+    }
+
+    #[test]
+    fn test_from_digest() {
+        let mut kc = Keccak::new(256);
+        kc.input([0x61u8]);
+        let d = Keccak256Output::from_digest(&mut kc).unwrap();
+        assert_eq!(d, Keccak256Output::digest([0x61u8]));
+        assert!(d != Keccak256Output::digest([0x62u8]));
+
+        let mut kc = Keccak::new(512);
+        assert!(Keccak256Output::from_digest(&mut kc).is_none());
+
+        let d = Shake128Output::digest([]);
+        assert_eq!(d.as_bytes()[0], 0x7f);
+
+        let mut sh = Shake::new(128);
+        assert_eq!(Shake128Output::from_reader(&mut sh), d);
+        assert!(Shake128Output::from_reader(&mut sh) != d);
+    }
+}
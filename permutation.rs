@@ -0,0 +1,454 @@
+use consts::*;
+use lanes;
+use backend;
+use zeroize::zeroize_slice;
+
+/// A fixed-width permutation as seen by the sponge and duplex layers: a
+/// `width()`-bit state addressed as bytes, that can be XORed into, read
+/// back and permuted.
+///
+/// The methods follow the SnP ("state and permutation") interface of the
+/// Keccak team's XKCP, so modes written against it (Keyak, Kravatte, the
+/// duplex) translate directly. Only `width`, `initialize`, `add_bytes`,
+/// `extract_bytes`, `permute` and `zeroize` need to be implemented; the
+/// rest have generic versions built on those.
+pub trait Permutation {
+    /// Width b of the state in bits.
+    fn width(&self) -> uint;
+
+    /// Rounds per call to `permute`.
+    fn rounds(&self) -> uint;
+
+    /// Set the state to all zeroes.
+    fn initialize(&mut self);
+
+    /// XOR `data` into the state, starting at byte `offset`.
+    fn add_bytes(&mut self, data: &[u8], offset: uint);
+
+    /// Copy `out.len()` bytes of the state, starting at byte `offset`, to
+    /// `out`.
+    fn extract_bytes(&self, out: &mut [u8], offset: uint);
+
+    fn permute(&mut self);
+
+    /// Like `initialize`, with writes that can't be optimized away, for
+    /// states that held secrets.
+    fn zeroize(&mut self);
+
+    /// Replace `data.len()` bytes of the state, starting at byte `offset`,
+    /// with `data`.
+    fn overwrite_bytes(&mut self, data: &[u8], offset: uint) {
+        let mut delta = [0u8, ..200];
+        for (i, chunk) in data.chunk_iter(200).enumerate() {
+            let n = chunk.len();
+            self.extract_bytes(delta.mut_slice_to(n), offset + 200 * i);
+            for (d, x) in delta.mut_iter().zip(chunk.iter()) {
+                *d ^= *x;
+            }
+            self.add_bytes(delta.slice_to(n), offset + 200 * i);
+        }
+    }
+
+    /// Set the first `byte_count` bytes of the state to zero.
+    fn overwrite_with_zeroes(&mut self, byte_count: uint) {
+        let mut current = [0u8, ..200];
+        let mut done = 0;
+        while done < byte_count {
+            let n = if byte_count - done < 200 { byte_count - done } else { 200 };
+            self.extract_bytes(current.mut_slice_to(n), done);
+            self.add_bytes(current.slice_to(n), done);
+            done += n;
+        }
+    }
+
+    /// Write `input` XORed with `out.len()` bytes of the state, starting at
+    /// byte `offset`, to `out`.
+    fn extract_and_add_bytes(&self, input: &[u8], out: &mut [u8], offset: uint) {
+        assert_eq!(input.len(), out.len());
+
+        self.extract_bytes(out, offset);
+        for (o, x) in out.mut_iter().zip(input.iter()) {
+            *o ^= *x;
+        }
+    }
+
+    /// Absorb whole blocks of `lane_count` lanes from the start of `data`,
+    /// permuting after each, for as long as a whole block is left. Returns
+    /// the number of bytes consumed.
+    fn fast_loop_absorb(&mut self, lane_count: uint, data: &[u8]) -> uint {
+        let block = lane_count * self.width() / 200;
+        let mut done = 0;
+
+        while done + block <= data.len() {
+            self.add_bytes(data.slice(done, done + block), 0);
+            self.permute();
+            done += block;
+        }
+
+        done
+    }
+}
+
+/// Keccak-f[1600], running on the backend this build selects.
+pub struct KeccakF1600 {
+    priv lanes: [u64, ..25],
+}
+
+impl KeccakF1600 {
+    pub fn new() -> KeccakF1600 {
+        KeccakF1600 { lanes: [0u64, ..25] }
+    }
+}
+
+impl Clone for KeccakF1600 {
+    fn clone(&self) -> KeccakF1600 {
+        KeccakF1600 { lanes: self.lanes }
+    }
+}
+
+impl Permutation for KeccakF1600 {
+    fn width(&self) -> uint { 1600 }
+
+    fn rounds(&self) -> uint { ROUND_N }
+
+    fn initialize(&mut self) {
+        self.lanes = [0u64, ..25];
+    }
+
+    fn add_bytes(&mut self, data: &[u8], offset: uint) {
+        lanes::xor_bytes(self.lanes, data, offset);
+    }
+
+    fn extract_bytes(&self, out: &mut [u8], offset: uint) {
+        lanes::extract_bytes(self.lanes, out, offset);
+    }
+
+    fn permute(&mut self) {
+        backend::permute_on_words(self.lanes);
+    }
+
+    fn zeroize(&mut self) {
+        zeroize_slice(self.lanes);
+    }
+
+    fn overwrite_bytes(&mut self, data: &[u8], offset: uint) {
+        lanes::overwrite_bytes(self.lanes, data, offset);
+    }
+
+    fn overwrite_with_zeroes(&mut self, byte_count: uint) {
+        lanes::overwrite_with_zeroes(self.lanes, byte_count);
+    }
+
+    fn extract_and_add_bytes(&self, input: &[u8], out: &mut [u8], offset: uint) {
+        lanes::extract_and_add_bytes(self.lanes, input, out, offset);
+    }
+
+    fn fast_loop_absorb(&mut self, lane_count: uint, data: &[u8]) -> uint {
+        let block = 8 * lane_count;
+        let mut done = 0;
+
+        while done + block <= data.len() {
+            backend::absorb(self.lanes, data.slice(done, done + block), lane_count);
+            done += block;
+        }
+
+        done
+    }
+}
+
+/// Keccak-p[1600, rounds]: the last `rounds` rounds of Keccak-f[1600], as
+/// used by KangarooTwelve (12) and the Keyak/Ketje family.
+pub struct KeccakP1600 {
+    priv lanes: [u64, ..25],
+    priv rounds: uint,
+}
+
+impl KeccakP1600 {
+    pub fn new(rounds: uint) -> KeccakP1600 {
+        assert!(rounds >= 1 && rounds <= ROUND_N);
+
+        KeccakP1600 { lanes: [0u64, ..25], rounds: rounds }
+    }
+}
+
+impl Clone for KeccakP1600 {
+    fn clone(&self) -> KeccakP1600 {
+        KeccakP1600 { lanes: self.lanes, rounds: self.rounds }
+    }
+}
+
+impl Permutation for KeccakP1600 {
+    fn width(&self) -> uint { 1600 }
+
+    fn rounds(&self) -> uint { self.rounds }
+
+    fn initialize(&mut self) {
+        self.lanes = [0u64, ..25];
+    }
+
+    fn add_bytes(&mut self, data: &[u8], offset: uint) {
+        lanes::xor_bytes(self.lanes, data, offset);
+    }
+
+    fn extract_bytes(&self, out: &mut [u8], offset: uint) {
+        lanes::extract_bytes(self.lanes, out, offset);
+    }
+
+    fn permute(&mut self) {
+        if self.rounds == ROUND_N {
+            backend::permute_on_words(self.lanes);
+        } else {
+            keccak_p_u64(self.lanes, self.rounds);
+        }
+    }
+
+    fn zeroize(&mut self) {
+        zeroize_slice(self.lanes);
+    }
+
+    fn overwrite_bytes(&mut self, data: &[u8], offset: uint) {
+        lanes::overwrite_bytes(self.lanes, data, offset);
+    }
+
+    fn overwrite_with_zeroes(&mut self, byte_count: uint) {
+        lanes::overwrite_with_zeroes(self.lanes, byte_count);
+    }
+
+    fn extract_and_add_bytes(&self, input: &[u8], out: &mut [u8], offset: uint) {
+        lanes::extract_and_add_bytes(self.lanes, input, out, offset);
+    }
+}
+
+// Keccak-p on 25 lanes of `$w` bits, ending with round `$max - 1`. The round
+// constants of the narrower instances are those of Keccak-f[1600]
+// truncated to the lane size.
+macro_rules! keccak_p_fn(
+    ($name:ident, $lane:ty, $w:expr, $max:expr) => (
+        fn $name(a: &mut [$lane], rounds: uint) {
+            #[inline(always)]
+            fn rol(x: $lane, n: uint) -> $lane {
+                let n = n % $w;
+                if n == 0 { x } else { (x << n) | (x >> ($w - n)) }
+            }
+
+            let mut c = [0 as $lane, ..5];
+            let mut b = [0 as $lane, ..25];
+
+            for round in range($max - rounds, $max) {
+                for x in range(0u, 5) {
+                    c[x] = a[x] ^ a[x + 5] ^ a[x + 10] ^ a[x + 15] ^ a[x + 20];
+                }
+
+                for i in range(0u, 25) {
+                    let x = i % 5;
+                    let d = c[(x + 4) % 5] ^ rol(c[(x + 1) % 5], 1);
+                    b[PI_DEST[i]] = rol(a[i] ^ d, RHO_OFFSETS[i]);
+                }
+
+                for y in range(0u, 5) {
+                    for x in range(0u, 5) {
+                        a[x + 5 * y] = b[x + 5 * y]
+                            ^ (!b[(x + 1) % 5 + 5 * y] & b[(x + 2) % 5 + 5 * y]);
+                    }
+                }
+
+                a[0] ^= ROUND_CONST[round] as $lane;
+            }
+        }
+    )
+)
+
+keccak_p_fn!(keccak_p_u64, u64, 64, 24)
+keccak_p_fn!(keccak_p_u32, u32, 32, 22)
+keccak_p_fn!(keccak_p_u16, u16, 16, 20)
+keccak_p_fn!(keccak_p_u8, u8, 8, 18)
+
+// Keccak-p[25 * $w, rounds] with the state held as 25 `$lane`s, each stored
+// least significant byte first.
+macro_rules! narrow_keccak_p(
+    ($name:ident, $lane:ty, $lane_bytes:expr, $max:expr, $permute:ident) => (
+        pub struct $name {
+            priv lanes: [$lane, ..25],
+            priv rounds: uint,
+        }
+
+        impl $name {
+            /// `rounds` of at most the nominal round count; passing the
+            /// nominal count gives Keccak-f.
+            pub fn new(rounds: uint) -> $name {
+                assert!(rounds >= 1 && rounds <= $max);
+
+                $name { lanes: [0 as $lane, ..25], rounds: rounds }
+            }
+        }
+
+        impl Clone for $name {
+            fn clone(&self) -> $name {
+                $name { lanes: self.lanes, rounds: self.rounds }
+            }
+        }
+
+        impl Permutation for $name {
+            fn width(&self) -> uint { 25 * 8 * $lane_bytes }
+
+            fn rounds(&self) -> uint { self.rounds }
+
+            fn initialize(&mut self) {
+                self.lanes = [0 as $lane, ..25];
+            }
+
+            fn add_bytes(&mut self, data: &[u8], offset: uint) {
+                assert!(offset + data.len() <= 25 * $lane_bytes);
+
+                for j in range(0, data.len()) {
+                    let k = offset + j;
+                    self.lanes[k / $lane_bytes] ^=
+                        (data[j] as $lane) << (8 * (k % $lane_bytes));
+                }
+            }
+
+            fn extract_bytes(&self, out: &mut [u8], offset: uint) {
+                assert!(offset + out.len() <= 25 * $lane_bytes);
+
+                for j in range(0, out.len()) {
+                    let k = offset + j;
+                    out[j] = (self.lanes[k / $lane_bytes] >> (8 * (k % $lane_bytes))) as u8;
+                }
+            }
+
+            fn permute(&mut self) {
+                $permute(self.lanes, self.rounds);
+            }
+
+            fn zeroize(&mut self) {
+                zeroize_slice(self.lanes);
+            }
+        }
+    )
+)
+
+narrow_keccak_p!(KeccakP800, u32, 4, 22, keccak_p_u32)
+narrow_keccak_p!(KeccakP400, u16, 2, 20, keccak_p_u16)
+narrow_keccak_p!(KeccakP200, u8, 1, 18, keccak_p_u8)
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::u8;
+    use std::vec;
+
+    fn from_hex(hex: &str) -> ~[u8] {
+        vec::from_fn(hex.len() / 2, |i| {
+            u8::from_str_radix(hex.slice(2 * i, 2 * i + 2), 16).unwrap()
+        })
+    }
+
+    fn permute_zero<P: Permutation>(p: &mut P) -> ~[u8] {
+        let mut out = vec::from_elem(p.width() / 8, 0u8);
+        p.permute();
+        p.extract_bytes(out, 0);
+        out
+    }
+
+    #[test]
+    fn test_keccak_p1600_full_rounds() {
+        use std::rand::{weak_rng, RngUtil};
+        use backend;
+
+        let mut rng = weak_rng();
+        let mut generic = [0u64, ..25];
+        for i in range(0, 25) {
+            generic[i] = rng.gen();
+        }
+        let mut fast = generic;
+
+        super::keccak_p_u64(generic, 24);
+        backend::permute_on_words(fast);
+        assert_eq!(generic.as_slice(), fast.as_slice());
+
+        let data = rng.gen_bytes(200);
+        let mut f = KeccakF1600::new();
+        let mut p = KeccakP1600::new(24);
+        f.add_bytes(data, 0);
+        p.add_bytes(data, 0);
+        f.permute();
+        p.permute();
+
+        let mut a = [0u8, ..200];
+        let mut b = [0u8, ..200];
+        f.extract_bytes(a, 0);
+        p.extract_bytes(b, 0);
+        assert_eq!(a.as_slice(), b.as_slice());
+    }
+
+    // The same checks for the lane-level methods of the 1600-bit types and
+    // the generic ones of the narrower widths.
+    fn check_snp<P: Permutation>(p: &mut P) {
+        let n = p.width() / 8;
+        let data = vec::from_fn(n, |i| (7 * i + 1) as u8);
+        let mut out = vec::from_elem(n, 0u8);
+
+        p.initialize();
+        p.add_bytes(data, 0);
+        p.overwrite_bytes([1u8, 2, 3], 5);
+        p.extract_bytes(out, 0);
+        assert_eq!(out.slice(5, 8), [1u8, 2, 3].as_slice());
+        assert_eq!(out.slice_to(5), data.slice_to(5));
+        assert_eq!(out.slice_from(8), data.slice_from(8));
+
+        p.overwrite_with_zeroes(6);
+        let mut sum = vec::from_elem(n - 4, 0u8);
+        p.extract_and_add_bytes(data.slice_from(4), sum, 4);
+        assert_eq!(sum[0], data[4]);
+        assert_eq!(sum[1], data[5]);
+        assert_eq!(sum[2], 2 ^ data[6]);
+
+        // Three blocks of two lanes, then one byte that is left over.
+        let lane_bytes = n / 25;
+        let input = vec::from_elem(6 * lane_bytes + 1, 0x5Au8);
+        p.initialize();
+        assert_eq!(p.fast_loop_absorb(2, input), 6 * lane_bytes);
+    }
+
+    #[test]
+    fn test_snp() {
+        check_snp(&mut KeccakF1600::new());
+        check_snp(&mut KeccakP1600::new(12));
+        check_snp(&mut KeccakP800::new(22));
+        check_snp(&mut KeccakP200::new(18));
+
+        // The lane-level fast loop matches the generic one.
+        let input = vec::from_fn(3 * 136 + 5, |i| i as u8);
+        let mut fast = KeccakF1600::new();
+        let mut generic = KeccakP1600::new(24);
+        assert_eq!(fast.fast_loop_absorb(17, input), 3 * 136);
+        let mut done = 0;
+        while done + 136 <= input.len() {
+            generic.add_bytes(input.slice(done, done + 136), 0);
+            generic.permute();
+            done += 136;
+        }
+
+        let mut a = [0u8, ..200];
+        let mut b = [0u8, ..200];
+        fast.extract_bytes(a, 0);
+        generic.extract_bytes(b, 0);
+        assert_eq!(a.as_slice(), b.as_slice());
+    }
+
+    // All-zero input. Computed with a separate implementation of
+    // Keccak-p[b, nr] that was checked against Keccak-f[1600].
+    #[test]
+    fn test_zero_state() {
+        assert_eq!(permute_zero(&mut KeccakP200::new(18)),
+            from_hex("3c2826841cb35c171eaae9b811134ceaa3852c69d2c5abafea"));
+        assert_eq!(permute_zero(&mut KeccakP400::new(20)),
+            from_hex("f509ac40a90ff5149fe8a0ecd15b7078f0ef8fbf3703526075dcc90e76e74652a159815d956d146e3e63ee58ff714c718eb3"));
+
+        let f800 = permute_zero(&mut KeccakP800::new(22));
+        assert_eq!(f800.slice_to(32), from_hex("5dd431e5fbc604f499bfa0232f45f8f142d0ff5178f539e5a7800bf0643697af"));
+
+        let k12 = permute_zero(&mut KeccakP1600::new(12));
+        assert_eq!(k12.slice_to(32), from_hex("1786a7b938545e8e1ed059f2506acdd9351fa952c6e7b887c5e0e4cd67e09310"));
+    }
+}
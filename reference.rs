@@ -1,4 +1,5 @@
 use consts::*;
+use lanes::{load_lanes, store_lanes};
 
 static NR_LANES: uint = 25;
 
@@ -43,28 +44,24 @@ pub fn absorb(state: &mut[u8], data: &[u8], lane_count: uint) {
 }
 
 pub fn extract_1024_bits(state: &[u8], data: &mut[u8]) {
-    use std::vec::raw::copy_memory;
-
-    unsafe { copy_memory(data, state, 128); }
+    extract(state, data, 16);
 }
 
 pub fn extract(state: &[u8], data: &mut[u8], lane_count: uint) {
-    use std::vec::raw::copy_memory;
-
-    unsafe { copy_memory(data, state, lane_count * 8); }
+    for i in range(0, lane_count * 8) {
+        data[i] = state[i];
+    }
 }
 
 pub fn permute(state: &mut[u8]) {
-    use std::cast::transmute;
+    let mut lanes = [0u64, ..25];
+    load_lanes(state, lanes);
 
-    unsafe {
-        let fixed = transmute::<&mut [u8], &mut [u64]> (state);
-        debug!("fixed.len() = %u \t state.len() = %u", fixed.len(), state.len());
+    dump(lanes, "Input of permutation");
+    permute_on_words(lanes);
+    dump(lanes, "State after permutation");
 
-        dump(fixed,"Input of permutation");
-        permute_on_words(fixed);
-        dump(fixed,"State after permutation");
-    }
+    store_lanes(lanes, state);
 }
 
 #[inline]
@@ -131,14 +128,14 @@ fn chi(A: &mut [u64]) {
 }
 
 fn iota(A: &mut [u64], index_round: uint) {
-    A[index!(0, 0)] ^= ROUND_CONST[index_round] as u64;
+    A[index!(0, 0)] ^= ROUND_CONST[index_round];
 }
 
 fn dump(state: &mut[u64], msg: &str) {
     debug!("%s: %?", msg, state);
 }
 
-fn permute_on_words(state: &mut[u64]) {
+pub fn permute_on_words(state: &mut[u64]) {
 
     for i in range(0, ROUND_N) {
         debug!("--- Round %u ---", i);
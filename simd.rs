@@ -0,0 +1,236 @@
+#[allow(non_camel_case_types)];
+
+use consts::*;
+
+// Keccak-f[1600] on x86_64 vector registers, used by `backend` in builds
+// with `--cfg simd`. Each plane is held as
+// vectors of consecutive lanes plus lane x = 4 in a scalar, so theta's
+// column parities and chi run several lanes per instruction; pi is a
+// scalar lane shuffle between the two.
+//
+// The u64x2 path uses SSE2, which every x86_64 CPU has. SSE2 shifts both
+// lanes of a vector by the same count, so rho, whose offsets differ from
+// lane to lane, rotates scalars there. AVX2 shifts each lane by its own
+// count and the u64x4 path does rho in vectors too. It is only compiled
+// with `--target-feature +avx2 --cfg 'target_feature="avx2"'` (the flag
+// doesn't set the cfg on this compiler), and such a build only runs on
+// AVX2 CPUs: LLVM may then use AVX2 instructions anywhere in the crate, so
+// no runtime check could make it safe on older ones.
+
+#[simd]
+#[deriving(Clone)]
+pub struct u64x2(u64, u64);
+
+#[cfg(target_feature = "avx2")]
+#[simd]
+#[deriving(Clone)]
+pub struct u64x4(u64, u64, u64, u64);
+
+static ONES: u64 = 0xFFFFFFFFFFFFFFFF;
+
+#[inline(always)]
+fn rol(a: u64, offset: uint) -> u64 {
+    if offset == 0 { a } else { (a << offset) | (a >> (64 - offset)) }
+}
+
+// Both lanes by the same `n`, between 1 and 63.
+#[inline(always)]
+fn rol2(v: u64x2, n: u64) -> u64x2 {
+    (v << u64x2(n, n)) | (v >> u64x2(64 - n, 64 - n))
+}
+
+// Masking the right shift makes a rotation by 0 come out as `v | v`.
+#[cfg(target_feature = "avx2")]
+#[inline(always)]
+fn rol4(v: u64x4, n: u64x4) -> u64x4 {
+    (v << n) | (v >> ((u64x4(64, 64, 64, 64) - n) & u64x4(63, 63, 63, 63)))
+}
+
+pub fn permute_on_words_sse2(state: &mut [u64]) {
+    // Plane y: lanes 0-1 in p01[y], lanes 2-3 in p23[y], lane 4 in p4[y].
+    let mut p01 = [u64x2(0, 0), ..5];
+    let mut p23 = [u64x2(0, 0), ..5];
+    let mut p4 = [0u64, ..5];
+
+    for y in range(0u, 5) {
+        let p = 5 * y;
+        p01[y] = u64x2(state[p], state[p + 1]);
+        p23[y] = u64x2(state[p + 2], state[p + 3]);
+        p4[y] = state[p + 4];
+    }
+
+    let ones = u64x2(ONES, ONES);
+    let mut b = [0u64, ..25];
+
+    for round in range(0, ROUND_N) {
+        let c01 = p01[0] ^ p01[1] ^ p01[2] ^ p01[3] ^ p01[4];
+        let c23 = p23[0] ^ p23[1] ^ p23[2] ^ p23[3] ^ p23[4];
+        let c4 = p4[0] ^ p4[1] ^ p4[2] ^ p4[3] ^ p4[4];
+        let u64x2(c0, c1) = c01;
+        let u64x2(c2, c3) = c23;
+
+        let d01 = u64x2(c4, c0) ^ rol2(u64x2(c1, c2), 1);
+        let d23 = u64x2(c1, c2) ^ rol2(u64x2(c3, c4), 1);
+        let d4 = c3 ^ rol(c0, 1);
+
+        for y in range(0u, 5) {
+            let p = 5 * y;
+            let u64x2(a0, a1) = p01[y] ^ d01;
+            let u64x2(a2, a3) = p23[y] ^ d23;
+            b[PI_DEST[p]] = rol(a0, RHO_OFFSETS[p]);
+            b[PI_DEST[p + 1]] = rol(a1, RHO_OFFSETS[p + 1]);
+            b[PI_DEST[p + 2]] = rol(a2, RHO_OFFSETS[p + 2]);
+            b[PI_DEST[p + 3]] = rol(a3, RHO_OFFSETS[p + 3]);
+            b[PI_DEST[p + 4]] = rol(p4[y] ^ d4, RHO_OFFSETS[p + 4]);
+        }
+
+        for y in range(0u, 5) {
+            let p = 5 * y;
+            p01[y] = u64x2(b[p], b[p + 1])
+                ^ ((u64x2(b[p + 1], b[p + 2]) ^ ones) & u64x2(b[p + 2], b[p + 3]));
+            p23[y] = u64x2(b[p + 2], b[p + 3])
+                ^ ((u64x2(b[p + 3], b[p + 4]) ^ ones) & u64x2(b[p + 4], b[p]));
+            p4[y] = b[p + 4] ^ (!b[p] & b[p + 1]);
+        }
+
+        p01[0] = p01[0] ^ u64x2(ROUND_CONST[round], 0);
+    }
+
+    for y in range(0u, 5) {
+        let p = 5 * y;
+        let u64x2(a0, a1) = p01[y];
+        let u64x2(a2, a3) = p23[y];
+        state[p] = a0;
+        state[p + 1] = a1;
+        state[p + 2] = a2;
+        state[p + 3] = a3;
+        state[p + 4] = p4[y];
+    }
+}
+
+#[cfg(target_feature = "avx2")]
+pub fn permute_on_words_avx2(state: &mut [u64]) {
+    // Plane y: lanes 0-3 in p03[y], lane 4 in p4[y].
+    let mut p03 = [u64x4(0, 0, 0, 0), ..5];
+    let mut p4 = [0u64, ..5];
+    let mut rho03 = [u64x4(0, 0, 0, 0), ..5];
+
+    for y in range(0u, 5) {
+        let p = 5 * y;
+        p03[y] = u64x4(state[p], state[p + 1], state[p + 2], state[p + 3]);
+        p4[y] = state[p + 4];
+        rho03[y] = u64x4(RHO_OFFSETS[p] as u64, RHO_OFFSETS[p + 1] as u64,
+                         RHO_OFFSETS[p + 2] as u64, RHO_OFFSETS[p + 3] as u64);
+    }
+
+    let ones = u64x4(ONES, ONES, ONES, ONES);
+    let mut b = [0u64, ..25];
+
+    for round in range(0, ROUND_N) {
+        let c03 = p03[0] ^ p03[1] ^ p03[2] ^ p03[3] ^ p03[4];
+        let c4 = p4[0] ^ p4[1] ^ p4[2] ^ p4[3] ^ p4[4];
+        let u64x4(c0, c1, c2, c3) = c03;
+
+        let d03 = u64x4(c4, c0, c1, c2) ^ rol4(u64x4(c1, c2, c3, c4), u64x4(1, 1, 1, 1));
+        let d4 = c3 ^ rol(c0, 1);
+
+        for y in range(0u, 5) {
+            let p = 5 * y;
+            let u64x4(a0, a1, a2, a3) = rol4(p03[y] ^ d03, rho03[y]);
+            b[PI_DEST[p]] = a0;
+            b[PI_DEST[p + 1]] = a1;
+            b[PI_DEST[p + 2]] = a2;
+            b[PI_DEST[p + 3]] = a3;
+            b[PI_DEST[p + 4]] = rol(p4[y] ^ d4, RHO_OFFSETS[p + 4]);
+        }
+
+        for y in range(0u, 5) {
+            let p = 5 * y;
+            p03[y] = u64x4(b[p], b[p + 1], b[p + 2], b[p + 3])
+                ^ ((u64x4(b[p + 1], b[p + 2], b[p + 3], b[p + 4]) ^ ones)
+                   & u64x4(b[p + 2], b[p + 3], b[p + 4], b[p]));
+            p4[y] = b[p + 4] ^ (!b[p] & b[p + 1]);
+        }
+
+        p03[0] = p03[0] ^ u64x4(ROUND_CONST[round], 0, 0, 0);
+    }
+
+    for y in range(0u, 5) {
+        let p = 5 * y;
+        let u64x4(a0, a1, a2, a3) = p03[y];
+        state[p] = a0;
+        state[p + 1] = a1;
+        state[p + 2] = a2;
+        state[p + 3] = a3;
+        state[p + 4] = p4[y];
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use opt64;
+    use extra::test::BenchHarness;
+
+    fn random_state() -> [u64, ..25] {
+        use std::rand::{weak_rng, RngUtil};
+
+        let mut rng = weak_rng();
+        let mut state = [0u64, ..25];
+        for i in range(0, 25) {
+            state[i] = rng.gen();
+        }
+        state
+    }
+
+    #[test]
+    fn test_sse2() {
+        for _ in range(0, 100) {
+            let mut fast = random_state();
+            let mut portable = fast;
+
+            permute_on_words_sse2(fast);
+            opt64::permute_on_words(portable);
+
+            assert_eq!(fast.as_slice(), portable.as_slice());
+        }
+    }
+
+    #[cfg(target_feature = "avx2")]
+    #[test]
+    fn test_avx2() {
+        for _ in range(0, 100) {
+            let mut fast = random_state();
+            let mut portable = fast;
+
+            permute_on_words_avx2(fast);
+            opt64::permute_on_words(portable);
+
+            assert_eq!(fast.as_slice(), portable.as_slice());
+        }
+    }
+
+    // To compare with `opt64`'s `bench_permute`.
+    #[bench]
+    fn bench_permute_sse2(bh: &mut BenchHarness) {
+        let mut state = [0u64, ..25];
+
+        do bh.iter {
+            permute_on_words_sse2(state);
+        }
+
+        bh.bytes = 200;
+    }
+
+    #[cfg(target_feature = "avx2")]
+    #[bench]
+    fn bench_permute_avx2(bh: &mut BenchHarness) {
+        let mut state = [0u64, ..25];
+
+        do bh.iter {
+            permute_on_words_avx2(state);
+        }
+
+        bh.bytes = 200;
+    }
+}
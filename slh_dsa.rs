@@ -0,0 +1,857 @@
+use fips202::Shake;
+use zeroize::{Zeroize, zeroize_slice};
+use std::vec;
+use std::vec::MutableCloneableVector;
+
+// Stateless hash-based signatures (FIPS 205, SLH-DSA) with the SHAKE
+// parameter sets. All tweakable hashes F, H and T_l are SHAKE256 over
+// PK.seed || ADRS || M, so each WOTS+ chain step is one short SHAKE256 call.
+
+#[deriving(Eq,Clone)]
+pub struct SlhDsaParams {
+    name: &'static str,
+    n: uint,
+    h: uint,
+    d: uint,
+    hp: uint,
+    a: uint,
+    k: uint,
+    m: uint,
+}
+
+pub static SLH_DSA_SHAKE_128S: SlhDsaParams = SlhDsaParams {
+    name: "SLH-DSA-SHAKE-128s", n: 16, h: 63, d: 7, hp: 9, a: 12, k: 14, m: 30 };
+pub static SLH_DSA_SHAKE_128F: SlhDsaParams = SlhDsaParams {
+    name: "SLH-DSA-SHAKE-128f", n: 16, h: 66, d: 22, hp: 3, a: 6, k: 33, m: 34 };
+pub static SLH_DSA_SHAKE_192S: SlhDsaParams = SlhDsaParams {
+    name: "SLH-DSA-SHAKE-192s", n: 24, h: 63, d: 7, hp: 9, a: 14, k: 17, m: 39 };
+pub static SLH_DSA_SHAKE_192F: SlhDsaParams = SlhDsaParams {
+    name: "SLH-DSA-SHAKE-192f", n: 24, h: 66, d: 22, hp: 3, a: 8, k: 33, m: 42 };
+pub static SLH_DSA_SHAKE_256S: SlhDsaParams = SlhDsaParams {
+    name: "SLH-DSA-SHAKE-256s", n: 32, h: 64, d: 8, hp: 8, a: 14, k: 22, m: 47 };
+pub static SLH_DSA_SHAKE_256F: SlhDsaParams = SlhDsaParams {
+    name: "SLH-DSA-SHAKE-256f", n: 32, h: 68, d: 17, hp: 4, a: 9, k: 35, m: 49 };
+
+// WOTS+ with w = 16 for every parameter set.
+static LG_W: uint = 4;
+static W: uint = 16;
+static LEN2: uint = 3;
+
+static WOTS_HASH: u32 = 0;
+static WOTS_PK: u32 = 1;
+static TREE: u32 = 2;
+static FORS_TREE: u32 = 3;
+static FORS_ROOTS: u32 = 4;
+static WOTS_PRF: u32 = 5;
+static FORS_PRF: u32 = 6;
+
+impl SlhDsaParams {
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn public_key_len(&self) -> uint {
+        2 * self.n
+    }
+
+    pub fn private_key_len(&self) -> uint {
+        4 * self.n
+    }
+
+    pub fn signature_len(&self) -> uint {
+        self.n * (1 + self.k * (1 + self.a) + self.h + self.d * self.wots_len())
+    }
+
+    fn wots_len(&self) -> uint {
+        2 * self.n + LEN2
+    }
+
+    fn xmss_sig_len(&self) -> uint {
+        (self.wots_len() + self.hp) * self.n
+    }
+
+    fn fors_sig_len(&self) -> uint {
+        self.k * (1 + self.a) * self.n
+    }
+}
+
+/// The 32-byte hash address ADRS (FIPS 205, section 4.2).
+struct Adrs {
+    bytes: [u8, ..32],
+}
+
+impl Adrs {
+    fn new() -> Adrs {
+        Adrs { bytes: [0u8, ..32] }
+    }
+
+    fn set_word(&mut self, off: uint, x: u32) {
+        self.bytes[off] = (x >> 24) as u8;
+        self.bytes[off + 1] = (x >> 16) as u8;
+        self.bytes[off + 2] = (x >> 8) as u8;
+        self.bytes[off + 3] = x as u8;
+    }
+
+    fn word(&self, off: uint) -> u32 {
+        (self.bytes[off] as u32 << 24) | (self.bytes[off + 1] as u32 << 16)
+            | (self.bytes[off + 2] as u32 << 8) | (self.bytes[off + 3] as u32)
+    }
+
+    fn set_layer(&mut self, layer: uint) {
+        self.set_word(0, layer as u32);
+    }
+
+    fn set_tree(&mut self, tree: u64) {
+        self.set_word(4, 0);
+        self.set_word(8, (tree >> 32) as u32);
+        self.set_word(12, tree as u32);
+    }
+
+    fn set_type_and_clear(&mut self, typ: u32) {
+        self.set_word(16, typ);
+        for i in range(20u, 32) {
+            self.bytes[i] = 0;
+        }
+    }
+
+    fn set_key_pair(&mut self, kp: u32) {
+        self.set_word(20, kp);
+    }
+
+    fn key_pair(&self) -> u32 {
+        self.word(20)
+    }
+
+    fn set_chain(&mut self, i: uint) {
+        self.set_word(24, i as u32);
+    }
+
+    fn set_tree_height(&mut self, z: uint) {
+        self.set_word(24, z as u32);
+    }
+
+    fn set_hash(&mut self, j: uint) {
+        self.set_word(28, j as u32);
+    }
+
+    fn set_tree_index(&mut self, i: u32) {
+        self.set_word(28, i);
+    }
+
+    fn tree_index(&self) -> u32 {
+        self.word(28)
+    }
+}
+
+fn shake256(n: uint, parts: &[&[u8]]) -> ~[u8] {
+    let mut sh = Shake::new(256);
+    for part in parts.iter() {
+        sh.input(*part).unwrap();
+    }
+
+    let mut out = vec::from_elem(n, 0u8);
+    sh.squeeze(out).unwrap();
+    out
+}
+
+/// Split `x` into `out_len` big-endian integers of `b` bits each.
+fn base_2b(x: &[u8], b: uint, out_len: uint) -> ~[uint] {
+    let mut input = 0;
+    let mut bits = 0u;
+    let mut total = 0u64;
+    let mut out = vec::with_capacity(out_len);
+
+    for _ in range(0, out_len) {
+        while bits < b {
+            total = (total << 8) | x[input] as u64;
+            input += 1;
+            bits += 8;
+        }
+        bits -= b;
+        out.push(((total >> bits) & ((1u64 << b) - 1)) as uint);
+    }
+
+    out
+}
+
+fn to_int(x: &[u8], bits: uint) -> u64 {
+    let mut total = 0u64;
+    for &b in x.iter() {
+        total = (total << 8) | b as u64;
+    }
+
+    if bits < 64 { total & ((1u64 << bits) - 1) } else { total }
+}
+
+/// Seeds and parameters shared by every hash in one key operation. Only
+/// signing and key generation fill in `sk_seed`.
+struct Ctx {
+    p: SlhDsaParams,
+    pk_seed: ~[u8],
+    sk_seed: ~[u8],
+}
+
+impl Ctx {
+    /// F, H and T_l: SHAKE256(PK.seed || ADRS || M).
+    fn thash(&self, adrs: &Adrs, m: &[u8]) -> ~[u8] {
+        shake256(self.p.n, [self.pk_seed.as_slice(), adrs.bytes.as_slice(), m])
+    }
+
+    fn thash2(&self, adrs: &Adrs, left: &[u8], right: &[u8]) -> ~[u8] {
+        shake256(self.p.n, [self.pk_seed.as_slice(), adrs.bytes.as_slice(), left, right])
+    }
+
+    /// PRF(PK.seed, SK.seed, ADRS)
+    fn prf(&self, adrs: &Adrs) -> ~[u8] {
+        shake256(self.p.n, [self.pk_seed.as_slice(), adrs.bytes.as_slice(),
+                            self.sk_seed.as_slice()])
+    }
+
+    fn chain(&self, x: &[u8], i: uint, s: uint, adrs: &mut Adrs) -> ~[u8] {
+        let mut tmp = x.to_owned();
+
+        for j in range(i, i + s) {
+            adrs.set_hash(j);
+            tmp = self.thash(adrs, tmp);
+        }
+
+        tmp
+    }
+
+    /// Base-w digits of the message followed by those of its checksum.
+    fn wots_digits(&self, m: &[u8]) -> ~[uint] {
+        let len1 = 2 * self.p.n;
+        let mut digits = base_2b(m, LG_W, len1);
+
+        let mut csum = 0u;
+        for &d in digits.iter() {
+            csum += W - 1 - d;
+        }
+        csum <<= (8 - (LEN2 * LG_W) % 8) % 8;
+
+        digits.push_all(base_2b([(csum >> 8) as u8, csum as u8], LG_W, LEN2));
+        digits
+    }
+
+    fn wots_sk_adrs(&self, adrs: &Adrs) -> Adrs {
+        let mut sk_adrs = *adrs;
+        sk_adrs.set_type_and_clear(WOTS_PRF);
+        sk_adrs.set_key_pair(adrs.key_pair());
+        sk_adrs
+    }
+
+    fn wots_compress(&self, adrs: &Adrs, tmp: &[u8]) -> ~[u8] {
+        let mut pk_adrs = *adrs;
+        pk_adrs.set_type_and_clear(WOTS_PK);
+        pk_adrs.set_key_pair(adrs.key_pair());
+        self.thash(&pk_adrs, tmp)
+    }
+
+    fn wots_pk_gen(&self, adrs: &mut Adrs) -> ~[u8] {
+        let mut sk_adrs = self.wots_sk_adrs(adrs);
+        let mut tmp = vec::with_capacity(self.p.wots_len() * self.p.n);
+
+        for i in range(0, self.p.wots_len()) {
+            sk_adrs.set_chain(i);
+            let sk = self.prf(&sk_adrs);
+            adrs.set_chain(i);
+            tmp.push_all(self.chain(sk, 0, W - 1, adrs));
+        }
+
+        self.wots_compress(adrs, tmp)
+    }
+
+    fn wots_sign(&self, m: &[u8], adrs: &mut Adrs) -> ~[u8] {
+        let digits = self.wots_digits(m);
+        let mut sk_adrs = self.wots_sk_adrs(adrs);
+        let mut sig = vec::with_capacity(self.p.wots_len() * self.p.n);
+
+        for i in range(0, self.p.wots_len()) {
+            sk_adrs.set_chain(i);
+            let sk = self.prf(&sk_adrs);
+            adrs.set_chain(i);
+            sig.push_all(self.chain(sk, 0, digits[i], adrs));
+        }
+
+        sig
+    }
+
+    fn wots_pk_from_sig(&self, sig: &[u8], m: &[u8], adrs: &mut Adrs) -> ~[u8] {
+        let n = self.p.n;
+        let digits = self.wots_digits(m);
+        let mut tmp = vec::with_capacity(self.p.wots_len() * n);
+
+        for i in range(0, self.p.wots_len()) {
+            adrs.set_chain(i);
+            tmp.push_all(self.chain(sig.slice(i * n, (i + 1) * n),
+                                    digits[i], W - 1 - digits[i], adrs));
+        }
+
+        self.wots_compress(adrs, tmp)
+    }
+
+    /// The root of the XMSS tree at `adrs` and the authentication path of
+    /// leaf `idx`, from one pass over the 2^hp leaves. A node is hashed
+    /// with the one below it on the stack as soon as both exist, so the
+    /// stack holds at most one node per height and each node is computed
+    /// once.
+    fn xmss_treehash(&self, idx: u32, adrs: &mut Adrs) -> (~[u8], ~[u8]) {
+        let n = self.p.n;
+        let hp = self.p.hp;
+        let mut auth = vec::from_elem(hp * n, 0u8);
+        let mut heights: ~[uint] = ~[];
+        let mut nodes: ~[~[u8]] = ~[];
+
+        for i in range(0, 1u32 << hp) {
+            adrs.set_type_and_clear(WOTS_HASH);
+            adrs.set_key_pair(i);
+            let mut node = self.wots_pk_gen(adrs);
+
+            // `node` is node j at height z.
+            let mut z = 0u;
+            let mut j = i;
+            loop {
+                if z < hp && j == (idx >> z) ^ 1 {
+                    auth.mut_slice(z * n, (z + 1) * n).copy_from(node);
+                }
+                if heights.len() == 0 || heights[heights.len() - 1] != z {
+                    break;
+                }
+
+                heights.pop();
+                let left = nodes.pop();
+                adrs.set_type_and_clear(TREE);
+                adrs.set_tree_height(z + 1);
+                adrs.set_tree_index(j >> 1);
+                node = self.thash2(adrs, left, node);
+                z += 1;
+                j >>= 1;
+            }
+
+            heights.push(z);
+            nodes.push(node);
+        }
+
+        (nodes.pop(), auth)
+    }
+
+    /// The XMSS signature of `m` by leaf `idx`, and the tree's root.
+    fn xmss_sign(&self, m: &[u8], idx: u32, adrs: &mut Adrs) -> (~[u8], ~[u8]) {
+        let (root, auth) = self.xmss_treehash(idx, adrs);
+
+        adrs.set_type_and_clear(WOTS_HASH);
+        adrs.set_key_pair(idx);
+        let mut sig = self.wots_sign(m, adrs);
+        sig.push_all(auth);
+        (sig, root)
+    }
+
+    fn xmss_pk_from_sig(&self, idx: u32, sig: &[u8], m: &[u8], adrs: &mut Adrs) -> ~[u8] {
+        let n = self.p.n;
+        let wots_len = self.p.wots_len() * n;
+
+        adrs.set_type_and_clear(WOTS_HASH);
+        adrs.set_key_pair(idx);
+        let mut node = self.wots_pk_from_sig(sig.slice_to(wots_len), m, adrs);
+        let auth = sig.slice_from(wots_len);
+
+        adrs.set_type_and_clear(TREE);
+        adrs.set_tree_index(idx);
+        for k in range(0, self.p.hp) {
+            let sibling = auth.slice(k * n, (k + 1) * n);
+            adrs.set_tree_height(k + 1);
+            node = if (idx >> k) & 1 == 0 {
+                let parent = adrs.tree_index() / 2;
+                adrs.set_tree_index(parent);
+                self.thash2(adrs, node, sibling)
+            } else {
+                let parent = (adrs.tree_index() - 1) / 2;
+                adrs.set_tree_index(parent);
+                self.thash2(adrs, sibling, node)
+            };
+        }
+
+        node
+    }
+
+    /// Each layer is one treehash pass of 2^hp WOTS+ key generations,
+    /// which also gives the root that the next layer up signs.
+    fn ht_sign(&self, m: &[u8], idx_tree: u64, idx_leaf: u32) -> ~[u8] {
+        let mut idx_tree = idx_tree;
+        let mut idx_leaf = idx_leaf;
+        let mut adrs = Adrs::new();
+        adrs.set_tree(idx_tree);
+
+        let (sig, root) = self.xmss_sign(m, idx_leaf, &mut adrs);
+        let mut sig = sig;
+        let mut root = root;
+
+        for j in range(1, self.p.d) {
+            idx_leaf = (idx_tree & ((1u64 << self.p.hp) - 1)) as u32;
+            idx_tree >>= self.p.hp;
+            adrs.set_layer(j);
+            adrs.set_tree(idx_tree);
+
+            let (xmss_sig, next) = self.xmss_sign(root, idx_leaf, &mut adrs);
+            sig.push_all(xmss_sig);
+            root = next;
+        }
+
+        sig
+    }
+
+    fn ht_verify(&self, m: &[u8], sig: &[u8], idx_tree: u64, idx_leaf: u32,
+                 pk_root: &[u8]) -> bool {
+        let xl = self.p.xmss_sig_len();
+        let mut idx_tree = idx_tree;
+        let mut idx_leaf = idx_leaf;
+        let mut adrs = Adrs::new();
+        adrs.set_tree(idx_tree);
+
+        let mut node = self.xmss_pk_from_sig(idx_leaf, sig.slice_to(xl), m, &mut adrs);
+
+        for j in range(1, self.p.d) {
+            idx_leaf = (idx_tree & ((1u64 << self.p.hp) - 1)) as u32;
+            idx_tree >>= self.p.hp;
+            adrs.set_layer(j);
+            adrs.set_tree(idx_tree);
+            node = self.xmss_pk_from_sig(idx_leaf, sig.slice(j * xl, (j + 1) * xl),
+                                         node, &mut adrs);
+        }
+
+        node.as_slice() == pk_root
+    }
+
+    fn fors_sk_gen(&self, adrs: &Adrs, idx: u32) -> ~[u8] {
+        let mut sk_adrs = *adrs;
+        sk_adrs.set_type_and_clear(FORS_PRF);
+        sk_adrs.set_key_pair(adrs.key_pair());
+        sk_adrs.set_tree_index(idx);
+        self.prf(&sk_adrs)
+    }
+
+    fn fors_node(&self, i: u32, z: uint, adrs: &mut Adrs) -> ~[u8] {
+        if z == 0 {
+            let sk = self.fors_sk_gen(adrs, i);
+            adrs.set_tree_height(0);
+            adrs.set_tree_index(i);
+            self.thash(adrs, sk)
+        } else {
+            let left = self.fors_node(2 * i, z - 1, adrs);
+            let right = self.fors_node(2 * i + 1, z - 1, adrs);
+            adrs.set_tree_height(z);
+            adrs.set_tree_index(i);
+            self.thash2(adrs, left, right)
+        }
+    }
+
+    fn fors_sign(&self, md: &[u8], adrs: &mut Adrs) -> ~[u8] {
+        let a = self.p.a;
+        let indices = base_2b(md, a, self.p.k);
+        let mut sig = vec::with_capacity(self.p.fors_sig_len());
+
+        for i in range(0, self.p.k) {
+            let leaf = indices[i] as u32;
+            sig.push_all(self.fors_sk_gen(adrs, ((i << a) as u32) + leaf));
+
+            for j in range(0, a) {
+                let s = (leaf >> j) ^ 1;
+                sig.push_all(self.fors_node(((i << (a - j)) as u32) + s, j, adrs));
+            }
+        }
+
+        sig
+    }
+
+    fn fors_pk_from_sig(&self, sig: &[u8], md: &[u8], adrs: &mut Adrs) -> ~[u8] {
+        let n = self.p.n;
+        let a = self.p.a;
+        let indices = base_2b(md, a, self.p.k);
+        let mut roots = vec::with_capacity(self.p.k * n);
+
+        for i in range(0, self.p.k) {
+            let leaf = indices[i] as u32;
+            let tree = sig.slice(i * (a + 1) * n, (i + 1) * (a + 1) * n);
+
+            adrs.set_tree_height(0);
+            adrs.set_tree_index(((i << a) as u32) + leaf);
+            let mut node = self.thash(adrs, tree.slice_to(n));
+
+            for j in range(0, a) {
+                let sibling = tree.slice((j + 1) * n, (j + 2) * n);
+                adrs.set_tree_height(j + 1);
+                node = if (leaf >> j) & 1 == 0 {
+                    let parent = adrs.tree_index() / 2;
+                    adrs.set_tree_index(parent);
+                    self.thash2(adrs, node, sibling)
+                } else {
+                    let parent = (adrs.tree_index() - 1) / 2;
+                    adrs.set_tree_index(parent);
+                    self.thash2(adrs, sibling, node)
+                };
+            }
+            roots.push_all(node);
+        }
+
+        let mut pk_adrs = *adrs;
+        pk_adrs.set_type_and_clear(FORS_ROOTS);
+        pk_adrs.set_key_pair(adrs.key_pair());
+        self.thash(&pk_adrs, roots)
+    }
+
+    /// H_msg(R, PK.seed, PK.root, M) split into the FORS message digest and
+    /// the hypertree leaf it is signed under.
+    fn digest(&self, r: &[u8], pk_root: &[u8], m: &[u8]) -> (~[u8], u64, u32) {
+        let p = &self.p;
+        let digest = shake256(p.m, [r, self.pk_seed.as_slice(), pk_root, m]);
+
+        let md_len = (p.k * p.a + 7) / 8;
+        let tree_bits = p.h - p.h / p.d;
+        let tree_len = (tree_bits + 7) / 8;
+        let leaf_bits = p.h / p.d;
+        let leaf_len = (leaf_bits + 7) / 8;
+
+        let md = digest.slice_to(md_len).to_owned();
+        let idx_tree = to_int(digest.slice(md_len, md_len + tree_len), tree_bits);
+        let idx_leaf = to_int(digest.slice(md_len + tree_len, md_len + tree_len + leaf_len),
+                              leaf_bits) as u32;
+
+        (md, idx_tree, idx_leaf)
+    }
+}
+
+// A signing `Ctx` holds a copy of SK.seed.
+#[cfg(zeroize)]
+impl Drop for Ctx {
+    fn drop(&self) {
+        use std::cast;
+
+        unsafe {
+            zeroize_slice(cast::transmute_mut(self).sk_seed);
+        }
+    }
+}
+
+/// M' = toByte(0, 1) || toByte(|ctx|, 1) || ctx || M, the pure (non-prehash)
+/// message encoding of FIPS 205, Algorithm 22.
+fn encode_message(msg: &[u8], ctx: &[u8]) -> ~[u8] {
+    let mut m = vec::with_capacity(2 + ctx.len() + msg.len());
+    m.push(0u8);
+    m.push(ctx.len() as u8);
+    m.push_all(ctx);
+    m.push_all(msg);
+    m
+}
+
+#[deriving(Eq,Clone)]
+pub struct SlhDsaPrivateKey {
+    priv params: SlhDsaParams,
+    priv sk_seed: ~[u8],
+    priv sk_prf: ~[u8],
+    priv pk_seed: ~[u8],
+    priv pk_root: ~[u8],
+}
+
+#[deriving(Eq,Clone)]
+pub struct SlhDsaPublicKey {
+    priv params: SlhDsaParams,
+    priv pk_seed: ~[u8],
+    priv pk_root: ~[u8],
+}
+
+impl SlhDsaPrivateKey {
+    /// Key generation from explicit n-byte seeds (slh_keygen_internal).
+    pub fn from_seeds(params: &SlhDsaParams, sk_seed: &[u8], sk_prf: &[u8],
+                      pk_seed: &[u8]) -> Option<SlhDsaPrivateKey> {
+        let n = params.n;
+        if sk_seed.len() != n || sk_prf.len() != n || pk_seed.len() != n {
+            return None;
+        }
+
+        let ctx = Ctx { p: params.clone(), pk_seed: pk_seed.to_owned(), sk_seed: sk_seed.to_owned() };
+        let mut adrs = Adrs::new();
+        adrs.set_layer(params.d - 1);
+        let (pk_root, _) = ctx.xmss_treehash(0, &mut adrs);
+
+        Some(SlhDsaPrivateKey {
+            params: params.clone(),
+            sk_seed: sk_seed.to_owned(),
+            sk_prf: sk_prf.to_owned(),
+            pk_seed: pk_seed.to_owned(),
+            pk_root: pk_root,
+        })
+    }
+
+    pub fn generate(params: &SlhDsaParams) -> SlhDsaPrivateKey {
+        use std::rand::{rng, RngUtil};
+
+        let mut r = rng();
+        let sk_seed = r.gen_bytes(params.n);
+        let sk_prf = r.gen_bytes(params.n);
+        let pk_seed = r.gen_bytes(params.n);
+
+        SlhDsaPrivateKey::from_seeds(params, sk_seed, sk_prf, pk_seed).unwrap()
+    }
+
+    /// SK.seed || SK.prf || PK.seed || PK.root
+    pub fn from_bytes(params: &SlhDsaParams, bytes: &[u8]) -> Option<SlhDsaPrivateKey> {
+        let n = params.n;
+        if bytes.len() != params.private_key_len() {
+            return None;
+        }
+
+        Some(SlhDsaPrivateKey {
+            params: params.clone(),
+            sk_seed: bytes.slice(0, n).to_owned(),
+            sk_prf: bytes.slice(n, 2 * n).to_owned(),
+            pk_seed: bytes.slice(2 * n, 3 * n).to_owned(),
+            pk_root: bytes.slice(3 * n, 4 * n).to_owned(),
+        })
+    }
+
+    pub fn to_bytes(&self) -> ~[u8] {
+        let mut out = vec::with_capacity(self.params.private_key_len());
+        out.push_all(self.sk_seed);
+        out.push_all(self.sk_prf);
+        out.push_all(self.pk_seed);
+        out.push_all(self.pk_root);
+        out
+    }
+
+    pub fn public_key(&self) -> SlhDsaPublicKey {
+        SlhDsaPublicKey {
+            params: self.params.clone(),
+            pk_seed: self.pk_seed.clone(),
+            pk_root: self.pk_root.clone(),
+        }
+    }
+
+    /// Hedged signature with fresh randomness. Returns `None` if `ctx` is
+    /// longer than 255 bytes.
+    pub fn sign(&self, msg: &[u8], ctx: &[u8]) -> Option<~[u8]> {
+        use std::rand::{rng, RngUtil};
+
+        let addrnd = rng().gen_bytes(self.params.n);
+        self.sign_with_randomness(msg, ctx, addrnd)
+    }
+
+    /// Deterministic variant, using PK.seed in place of fresh randomness.
+    pub fn sign_deterministic(&self, msg: &[u8], ctx: &[u8]) -> Option<~[u8]> {
+        self.sign_with_randomness(msg, ctx, self.pk_seed)
+    }
+
+    fn sign_with_randomness(&self, msg: &[u8], ctx: &[u8], opt_rand: &[u8]) -> Option<~[u8]> {
+        if ctx.len() > 255 {
+            return None;
+        }
+        let m = encode_message(msg, ctx);
+
+        let c = Ctx {
+            p: self.params.clone(),
+            pk_seed: self.pk_seed.clone(),
+            sk_seed: self.sk_seed.clone(),
+        };
+
+        let r = shake256(self.params.n, [self.sk_prf.as_slice(), opt_rand, m.as_slice()]);
+        let (md, idx_tree, idx_leaf) = c.digest(r, self.pk_root, m);
+
+        let mut adrs = Adrs::new();
+        adrs.set_tree(idx_tree);
+        adrs.set_type_and_clear(FORS_TREE);
+        adrs.set_key_pair(idx_leaf);
+
+        let sig_fors = c.fors_sign(md, &mut adrs);
+        let pk_fors = c.fors_pk_from_sig(sig_fors, md, &mut adrs);
+        let sig_ht = c.ht_sign(pk_fors, idx_tree, idx_leaf);
+
+        let mut sig = vec::with_capacity(self.params.signature_len());
+        sig.push_all(r);
+        sig.push_all(sig_fors);
+        sig.push_all(sig_ht);
+
+        Some(sig)
+    }
+}
+
+// Only the SK half is secret; PK.seed and PK.root are left for
+// `public_key`.
+impl Zeroize for SlhDsaPrivateKey {
+    fn zeroize(&mut self) {
+        zeroize_slice(self.sk_seed);
+        zeroize_slice(self.sk_prf);
+    }
+}
+
+#[cfg(zeroize)]
+impl Drop for SlhDsaPrivateKey {
+    fn drop(&self) {
+        use std::cast;
+
+        unsafe {
+            cast::transmute_mut(self).zeroize();
+        }
+    }
+}
+
+impl SlhDsaPublicKey {
+    /// PK.seed || PK.root
+    pub fn from_bytes(params: &SlhDsaParams, bytes: &[u8]) -> Option<SlhDsaPublicKey> {
+        let n = params.n;
+        if bytes.len() != params.public_key_len() {
+            return None;
+        }
+
+        Some(SlhDsaPublicKey {
+            params: params.clone(),
+            pk_seed: bytes.slice_to(n).to_owned(),
+            pk_root: bytes.slice_from(n).to_owned(),
+        })
+    }
+
+    pub fn to_bytes(&self) -> ~[u8] {
+        let mut out = vec::with_capacity(self.params.public_key_len());
+        out.push_all(self.pk_seed);
+        out.push_all(self.pk_root);
+        out
+    }
+
+    pub fn verify(&self, msg: &[u8], sig: &[u8], ctx: &[u8]) -> bool {
+        let p = &self.params;
+
+        if ctx.len() > 255 || sig.len() != p.signature_len() {
+            return false;
+        }
+        let m = encode_message(msg, ctx);
+
+        let c = Ctx { p: p.clone(), pk_seed: self.pk_seed.clone(), sk_seed: ~[] };
+
+        let r = sig.slice_to(p.n);
+        let sig_fors = sig.slice(p.n, p.n + p.fors_sig_len());
+        let sig_ht = sig.slice_from(p.n + p.fors_sig_len());
+        let (md, idx_tree, idx_leaf) = c.digest(r, self.pk_root, m);
+
+        let mut adrs = Adrs::new();
+        adrs.set_tree(idx_tree);
+        adrs.set_type_and_clear(FORS_TREE);
+        adrs.set_key_pair(idx_leaf);
+
+        let pk_fors = c.fors_pk_from_sig(sig_fors, md, &mut adrs);
+        c.ht_verify(pk_fors, sig_ht, idx_tree, idx_leaf, self.pk_root)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::vec;
+
+    fn seed(n: uint, base: u8) -> ~[u8] {
+        vec::from_fn(n, |i| base + i as u8)
+    }
+
+    fn from_hex(line: &str) -> ~[u8] {
+        use std::u8;
+        use std::str;
+
+        line.split_iter(' ').collect::<~[&str]>()[2]
+            .iter()
+            .collect::<~[char]>()
+            .chunk_iter(2)
+            .map(|cs| u8::from_str_radix(str::from_chars(cs), 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_sizes() {
+        // FIPS 205, Table 2
+        let table = [
+            (&SLH_DSA_SHAKE_128S, 32u, 7856u),
+            (&SLH_DSA_SHAKE_128F, 32, 17088),
+            (&SLH_DSA_SHAKE_192S, 48, 16224),
+            (&SLH_DSA_SHAKE_192F, 48, 35664),
+            (&SLH_DSA_SHAKE_256S, 64, 29792),
+            (&SLH_DSA_SHAKE_256F, 64, 49856),
+        ];
+
+        for &(p, pk_len, sig_len) in table.iter() {
+            assert_eq!(p.public_key_len(), pk_len);
+            assert_eq!(p.signature_len(), sig_len);
+        }
+    }
+
+    #[test]
+    fn test_sign_verify_128f() {
+        let p = &SLH_DSA_SHAKE_128F;
+        let sk = SlhDsaPrivateKey::from_seeds(p, seed(16, 0), seed(16, 0x20), seed(16, 0x40)).unwrap();
+        let pk = SlhDsaPublicKey::from_bytes(p, sk.public_key().to_bytes()).unwrap();
+        assert!(pk == sk.public_key());
+        assert!(SlhDsaPrivateKey::from_bytes(p, sk.to_bytes()).unwrap() == sk);
+
+        let msg = "release 1.2.3".as_bytes();
+        let ctx = "fw".as_bytes();
+
+        let sig = sk.sign_deterministic(msg, ctx).unwrap();
+        assert_eq!(sig.len(), p.signature_len());
+        assert_eq!(sig, sk.sign_deterministic(msg, ctx).unwrap());
+        assert!(pk.verify(msg, sig, ctx));
+        assert!(!pk.verify(msg, sig, []));
+        assert!(!pk.verify("release 1.2.4".as_bytes(), sig, ctx));
+
+        let mut bad = sig.clone();
+        bad[p.n + 5] ^= 0x10;
+        assert!(!pk.verify(msg, bad, ctx));
+
+        let hedged = sk.sign(msg, ctx).unwrap();
+        assert!(hedged != sig);
+        assert!(pk.verify(msg, hedged, ctx));
+
+        assert!(sk.sign(msg, vec::from_elem(256, 0u8)).is_none());
+    }
+
+    #[test]
+    fn test_zeroize() {
+        use zeroize::Zeroize;
+
+        let p = &SLH_DSA_SHAKE_128F;
+        let mut sk = SlhDsaPrivateKey::from_seeds(p, seed(16, 1), seed(16, 2), seed(16, 3)).unwrap();
+        let pk = sk.public_key();
+
+        sk.zeroize();
+        assert!(sk.to_bytes().slice_to(2 * p.n).iter().all(|&b| b == 0));
+        assert!(sk.public_key() == pk);
+    }
+
+    #[test]
+    fn test_verify_128s_vector() {
+        use std::io;
+        use std::path::PosixPath;
+
+        let p = &SLH_DSA_SHAKE_128S;
+        let r = match io::file_reader(&PosixPath("test_vectors/SLH-DSA-SHAKE-128s_Verify.txt")) {
+            Ok(reader) => reader,
+            Err(msg) => fail!(msg)
+        };
+
+        let mut pk = ~[];
+        let mut msg = ~[];
+        let mut sig = ~[];
+
+        do r.each_line |line| {
+            if line.starts_with("PK") {
+                pk = from_hex(line);
+            } else if line.starts_with("Msg") {
+                msg = from_hex(line);
+            } else if line.starts_with("Sig") {
+                sig = from_hex(line);
+            }
+            true
+        };
+
+        let key = SlhDsaPublicKey::from_bytes(p, pk).unwrap();
+        assert!(key.verify(msg, sig, []));
+
+        let mid = sig.len() / 2;
+        sig[mid] ^= 0x01;
+        assert!(!key.verify(msg, sig, []));
+    }
+}
@@ -1,9 +1,13 @@
 use consts::*;
-use reference;
-
-#[packed]
-pub struct SpongeState {
-    state: [u8, ..PERM_SIZE_IN_BYTES],
+use permutation::{Permutation, KeccakF1600};
+use zeroize::{Zeroize, zeroize_slice};
+use std::rt::io;
+use std::vec::MutableCloneableVector;
+
+/// The sponge construction on any permutation `P`, with `rate + capacity`
+/// equal to its width.
+pub struct Sponge<P> {
+    state: P,
     data_queue: [u8, ..MAX_RATE_IN_BYTES],
     rate: uint,
     capacity: uint,
@@ -15,19 +19,45 @@ pub struct SpongeState {
 
 #[deriving(Eq,TotalEq,ToStr,Clone)]
 pub enum SpongeError {
-    Success,
-    Failure,
+    /// Rate and capacity don't add up to the permutation width, or the rate
+    /// is not a whole number of bytes, or is too small to hold the padding.
+    InvalidParameters,
+    /// The rate is more than `MAX_RATE`, the 1536 bits of the block queue,
+    /// so a capacity below 64 on Keccak-f[1600].
+    RateTooLarge,
+    /// Input after the first output was read.
+    AbsorbAfterSqueeze,
+    /// A byte-oriented call while the output stream stands mid-byte.
+    UnalignedBitState,
+    /// The output buffer is not the size of the digest, or longer than a
+    /// duplex call returns.
+    OutputLengthMismatch,
+    /// A buffer holds fewer bits than the length given with it.
+    BufferTooSmall,
+    /// More input than a single duplex call takes.
+    InputTooLong,
+    /// A midstate record that is malformed or doesn't fit the instance
+    /// importing it.
+    InvalidMidstate,
 }
 
-impl SpongeState {
-    pub fn new(rate: uint, capacity: uint) -> SpongeState {
-        assert!(rate + capacity == 1600);
-        assert!(rate % 64 == 0);
+/// The sponge on Keccak-f[1600] that the hash functions use.
+pub type SpongeState = Sponge<KeccakF1600>;
+
+impl<P: Permutation> Sponge<P> {
+    pub fn new(perm: P, rate: uint, capacity: uint) -> Result<Sponge<P>, SpongeError> {
+        if rate + capacity != perm.width() || rate % 8 != 0 || rate == 0 {
+            return Err(InvalidParameters);
+        }
+        if rate > MAX_RATE {
+            return Err(RateTooLarge);
+        }
 
-        debug!("Rate = %u", rate);
+        let mut perm = perm;
+        perm.initialize();
 
-        SpongeState {
-            state: [0u8, ..PERM_SIZE_IN_BYTES],
+        Ok(Sponge {
+            state: perm,
             data_queue: [0u8, ..MAX_RATE_IN_BYTES],
             rate: rate,
             capacity: capacity,
@@ -35,98 +65,62 @@ impl SpongeState {
             bits_in_queue: 0,
             bits_for_squeezing: 0,
             squeezing: false
+        })
+    }
+
+    /// Go back to the empty state, keeping the parameters.
+    pub fn reset(&mut self) {
+        self.state.initialize();
+        for b in self.data_queue.mut_iter() {
+            *b = 0;
         }
+        self.bits_in_queue = 0;
+        self.squeezing = false;
+        self.bits_for_squeezing = 0;
     }
 
-    pub fn absorb(&mut self, data: &[u8], data_bit_len: uint) -> SpongeError {
-        use std::vec::raw::*;
-        use std::vec::*;
+    /// Absorb the first `data_bit_len` bits of `data`: bit i is bit i % 8 of
+    /// byte i / 8. Calls can end on any bit and the next one continues
+    /// from there.
+    pub fn absorb(&mut self, data: &[u8], data_bit_len: uint) -> Result<(), SpongeError> {
+        if self.squeezing {
+            return Err(AbsorbAfterSqueeze);
+        }
+        if data_bit_len > data.len() * 8 {
+            return Err(BufferTooSmall);
+        }
 
         if self.bits_in_queue % 8 != 0 {
-            return Failure;
-        }
-        if self.squeezing {
-            return Failure;
+            // The queue ends mid-byte, so every input byte straddles two
+            // queue bytes; shift it in a byte at a time.
+            let mut i = 0u;
+            while i < data_bit_len {
+                let n = if data_bit_len - i < 8 { data_bit_len - i } else { 8 };
+                self.queue_bits(data[i/8], n);
+                i += n;
+            }
+            return Ok(());
         }
 
         let mut whole_blocks;
-        let mut cur_data;
         let mut part_block;
         let mut part_byte;
         let mut i = 0u;
 
         while i < data_bit_len {
-            debug!("data_bit_len = %u : self.rate = %u", data_bit_len, self.rate);
             if (self.bits_in_queue == 0) && (data_bit_len >= self.rate)
                 && (i <=  (data_bit_len - self.rate)) {
                 whole_blocks = (data_bit_len - i) / self.rate;
-                cur_data = to_ptr(data) + i/8;
-
-                unsafe {
-                    match self.rate {
-                        576 => {
-                            for _ in range(0, whole_blocks) {
-                                do buf_as_slice(cur_data, 576/8) |buf| {
-                                    debug!("Block to be absorbed: %?", buf);
-                                    reference::absorb_576_bits(self.state, buf);
-                                }
-                                cur_data = cur_data + 576/8;
-                            }
-                        }
-                        832 => {
-                            for _ in range(0, whole_blocks) {
-                                do buf_as_slice(cur_data, 832/8) |buf| {
-                                    debug!("Block to be absorbed: %?", buf);
-                                    reference::absorb_832_bits(self.state, buf);
-                                }
-                                cur_data = cur_data + 832/8;
-                            }
-                        }
-                        1024 => {
-                            for _ in range(0, whole_blocks) {
-                                do buf_as_slice(cur_data, 1024/8) |buf| {
-                                    debug!("Block to be absorbed: %?", buf);
-                                    reference::absorb_1024_bits(self.state, buf);
-                                }
-                                cur_data = cur_data + 1024/8;
-                            }
-                        }
-                        1088 => {
-                            for _ in range(0, whole_blocks) {
-                                do buf_as_slice(cur_data, 1088/8) |buf| {
-                                    debug!("Block to be absorbed: %?", buf);
-                                    reference::absorb_1088_bits(self.state, buf);
-                                }
-                                cur_data = cur_data + 1088/8;
-                            }
-                        }
-                        1152 => {
-                            for _ in range(0, whole_blocks) {
-                                do buf_as_slice(cur_data, 1152/8) |buf| {
-                                    debug!("Block to be absorbed: %?", buf);
-                                    reference::absorb_1152_bits(self.state, buf);
-                                }
-                                cur_data = cur_data + 1152/8;
-                            }
-                        }
-                        1344 => {
-                            for _ in range(0, whole_blocks) {
-                                do buf_as_slice(cur_data, 1344/8) |buf| {
-                                    debug!("Block to be absorbed: %?", buf);
-                                    reference::absorb_1344_bits(self.state, buf);
-                                }
-                                cur_data = cur_data + 1344/8;
-                            }
-                        }
-                        n => {
-                            for _ in range(0, whole_blocks) {
-                                do buf_as_slice(cur_data, n/8) |buf| {
-                                    debug!("Block to be absorbed: %?", buf);
-                                    reference::absorb(self.state, buf, self.rate / 64);
-                                }
-                                cur_data = cur_data + self.rate/8;
-                            }
-                        }
+
+                let blocks = data.slice(i/8, (i + whole_blocks * self.rate)/8);
+                let lane_bits = self.state.width() / 25;
+
+                if self.rate % lane_bits == 0 {
+                    self.state.fast_loop_absorb(self.rate / lane_bits, blocks);
+                } else {
+                    for block in blocks.chunk_iter(self.rate/8) {
+                        self.state.add_bytes(block, 0);
+                        self.state.permute();
                     }
                 }
                 i += whole_blocks * self.rate;
@@ -137,13 +131,8 @@ impl SpongeState {
                 }
                 part_byte = part_block % 8;
                 part_block -= part_byte;
-                unsafe {
-                    let dq: &mut[u8] = self.data_queue;
-                    copy_memory(dq
-                        .mut_slice_from(self.bits_in_queue/8),
-                        data.slice_from(i/8),
-                        part_block/8);
-                }
+                self.data_queue.mut_slice_from(self.bits_in_queue/8)
+                    .copy_from(data.slice(i/8, (i + part_block)/8));
                 self.bits_in_queue += part_block;
                 i += part_block;
 
@@ -157,119 +146,200 @@ impl SpongeState {
                     i += part_byte;
                 }
             }
+        }
 
-            debug!("(At the end of loop iteration) i = %u",i);
+        Ok(())
+    }
+
+    // Append the low `n` bits of `byte` to the queue, absorbing it when it
+    // fills up.
+    fn queue_bits(&mut self, byte: u8, n: uint) {
+        let room = self.rate - self.bits_in_queue;
+        if n > room {
+            self.queue_bits(byte, room);
+            self.queue_bits(byte >> room, n - room);
+            return;
         }
 
-        Success
+        let pos = self.bits_in_queue;
+        let shift = pos % 8;
+        let bits = ((byte as uint) & ((1 << n) - 1)) << shift;
+
+        if shift == 0 {
+            self.data_queue[pos/8] = bits as u8;
+        } else {
+            self.data_queue[pos/8] |= bits as u8;
+        }
+        if shift + n > 8 {
+            self.data_queue[pos/8 + 1] = (bits >> 8) as u8;
+        }
+
+        self.bits_in_queue += n;
+        if self.bits_in_queue == self.rate {
+            self.absorb_queue();
+        }
     }
 
     fn pad_and_switch_to_squeeze(&mut self) {
-        use std::ptr::set_memory;
-
-        debug!("Bits in queue: %u %?",self.bits_in_queue, self.data_queue);
         if self.bits_in_queue + 1 == self.rate {
             self.data_queue[self.bits_in_queue/8] |= 1 << (self.bits_in_queue % 8);
             self.absorb_queue();
 
-            do self.data_queue.as_mut_buf |buf, _| {
-                unsafe {
-                    set_memory(buf, 0u8, self.rate / 8);
-                }
+            for b in self.data_queue.mut_slice_to(self.rate/8).mut_iter() {
+                *b = 0;
             }
         } else {
-            debug!("(self.bits_in_queue + 7)/8: %u | self.rate/8 - (self.bits_in_queue + 7)/8: %u ",
-                (self.bits_in_queue + 7)/8, self.rate/8 - (self.bits_in_queue + 7)/8 );
-
-            do self.data_queue.as_mut_buf |buf, _| {
-                unsafe {
-                    set_memory(buf + (self.bits_in_queue + 7)/8,
-                        0u8,
-                        self.rate/8 - (self.bits_in_queue + 7)/8);
-                }
+            for b in self.data_queue.mut_slice((self.bits_in_queue + 7)/8, self.rate/8).mut_iter() {
+                *b = 0;
             }
             self.data_queue[self.bits_in_queue/8] |= 1 << (self.bits_in_queue % 8);
         }
         self.data_queue[(self.rate-1)/8] |= 1 << ((self.rate-1) % 8);
         self.absorb_queue();
 
-        debug!("--- Switching to squeezing phase ---");
-
-        if self.rate == 1024 {
-            debug!("Fast 1024");
-            reference::extract_1024_bits(self.state, self.data_queue);
-            self.bits_for_squeezing = 1024;
-        } else {
-            debug!("Other rate");
-            reference::extract(self.state, self.data_queue, self.rate/64);
-            self.bits_for_squeezing = self.rate;
-        }
-
-        debug!("Block available for squeezing: %?", self.data_queue.slice_to(self.bits_for_squeezing / 8));
+        self.bits_for_squeezing = self.rate;
         self.squeezing = true;
     }
 
-    pub fn squeeze(&mut self, out: &mut[u8], out_len: uint) -> SpongeError {
-        use std::vec::raw::copy_memory;
+    /// Write the next `out_len` bits of output to `out`, bit i to bit i % 8
+    /// of byte i / 8. If `out_len` is not a multiple of 8, the unused high
+    /// bits of the last byte are cleared, and the next call continues with
+    /// the bit after the last one written.
+    pub fn squeeze(&mut self, out: &mut[u8], out_len: uint) -> Result<(), SpongeError> {
+        if out_len > out.len() * 8 {
+            return Err(BufferTooSmall);
+        }
 
         if !self.squeezing {
             self.pad_and_switch_to_squeeze();
         }
 
-        if out_len % 8 != 0 {
-            return Failure;
+        if self.bits_for_squeezing % 8 != 0 {
+            // An earlier call stopped mid-byte, so output bytes no longer
+            // line up with state bytes.
+            let mut i = 0u;
+            while i < out_len {
+                let n = if out_len - i < 8 { out_len - i } else { 8 };
+                out[i/8] = self.squeeze_bits(n);
+                i += n;
+            }
+            return Ok(());
         }
 
+        let whole_len = out_len - out_len % 8;
         let mut i = 0;
         let mut part_block;
 
-        while i < out_len {
+        while i < whole_len {
             if self.bits_for_squeezing == 0 {
-                reference::permute(self.state);
-
-                if self.rate == 1024 {
-                    reference::extract_1024_bits(self.state, self.data_queue);
-                    self.bits_for_squeezing = 1024;
-                } else {
-                    reference::extract(self.state, self.data_queue, self.rate / 64);
-                    self.bits_for_squeezing = self.rate;
-                }
-
-                debug!("Blocks available for squeezing: %?",
-                    self.data_queue.slice_to(self.bits_for_squeezing / 8));
+                self.state.permute();
+                self.bits_for_squeezing = self.rate;
             }
 
             part_block = self.bits_for_squeezing;
-            if part_block > out_len - i {
-                part_block = out_len - i;
+            if part_block > whole_len - i {
+                part_block = whole_len - i;
             }
 
-            unsafe {
-                copy_memory(out.mut_slice_from(i/8),
-                    self.data_queue.slice_from((self.rate - self.bits_for_squeezing) / 8),
-                    part_block/8);
-            }
+            let start = (self.rate - self.bits_for_squeezing) / 8;
+            self.state.extract_bytes(out.mut_slice(i/8, (i + part_block)/8), start);
             self.bits_for_squeezing -= part_block;
             i += part_block;
         }
 
-        Success
+        if out_len % 8 != 0 {
+            out[whole_len/8] = self.squeeze_bits(out_len % 8);
+        }
+
+        Ok(())
+    }
+
+    /// Whether the output read so far ends mid-byte.
+    pub fn is_output_unaligned(&self) -> bool {
+        self.squeezing && self.bits_for_squeezing % 8 != 0
+    }
+
+    // The next `n` (at most 8) output bits, in the low bits of a byte.
+    fn squeeze_bits(&mut self, n: uint) -> u8 {
+        if self.bits_for_squeezing == 0 {
+            self.state.permute();
+            self.bits_for_squeezing = self.rate;
+        }
+
+        let avail = self.bits_for_squeezing;
+        if n > avail {
+            let low = self.squeeze_bits(avail);
+            let high = self.squeeze_bits(n - avail);
+            return low | (high << avail);
+        }
+
+        let pos = self.rate - self.bits_for_squeezing;
+        let mut bytes = [0u8, ..2];
+        let count = if pos % 8 + n > 8 { 2 } else { 1 };
+        self.state.extract_bytes(bytes.mut_slice_to(count), pos / 8);
+
+        let word = (bytes[0] as uint | (bytes[1] as uint << 8)) >> (pos % 8);
+        self.bits_for_squeezing -= n;
+        (word & ((1 << n) - 1)) as u8
     }
 
     fn absorb_queue(&mut self) {
-        debug!("Absorbing Queue");
-        debug!("Block to be absorbed: %?", self.data_queue.slice_to(self.rate/8));
-        match self.rate {
-            576 => reference::absorb_576_bits(self.state, self.data_queue),
-            832 => reference::absorb_832_bits(self.state, self.data_queue),
-            1024 => reference::absorb_1024_bits(self.state, self.data_queue),
-            1088 => reference::absorb_1088_bits(self.state, self.data_queue),
-            1152 => reference::absorb_1152_bits(self.state, self.data_queue),
-            1344 => reference::absorb_1344_bits(self.state, self.data_queue),
-            _ => reference::absorb(self.state, self.data_queue, self.rate / 64)
+        self.state.add_bytes(self.data_queue.slice_to(self.rate/8), 0);
+        self.state.permute();
+
+        self.bits_in_queue = 0;
+    }
+}
+
+// Absorbs whole bytes; fails the task on input after output, like the
+// hashers' `Digest::input`.
+impl<P: Permutation> io::Writer for Sponge<P> {
+    fn write(&mut self, buf: &[u8]) {
+        match self.absorb(buf, buf.len() * 8) {
+            Ok(()) => (),
+            Err(e) => fail!(e.to_str())
+        }
+    }
+
+    fn flush(&mut self) {}
+}
+
+// A copy of the whole state, including a partly filled queue, so a shared
+// prefix can be absorbed once and the copies continue independently.
+impl<P: Permutation + Clone> Clone for Sponge<P> {
+    fn clone(&self) -> Sponge<P> {
+        Sponge {
+            state: self.state.clone(),
+            data_queue: self.data_queue,
+            rate: self.rate,
+            capacity: self.capacity,
+            bits_in_queue: self.bits_in_queue,
+            fixed_out_len: self.fixed_out_len,
+            squeezing: self.squeezing,
+            bits_for_squeezing: self.bits_for_squeezing,
         }
+    }
+}
 
+impl<P: Permutation> Zeroize for Sponge<P> {
+    fn zeroize(&mut self) {
+        self.state.zeroize();
+        zeroize_slice(self.data_queue);
         self.bits_in_queue = 0;
+        self.squeezing = false;
+        self.bits_for_squeezing = 0;
+    }
+}
+
+#[cfg(zeroize)]
+#[unsafe_destructor]
+impl<P: Permutation> Drop for Sponge<P> {
+    fn drop(&self) {
+        use std::cast;
+
+        unsafe {
+            cast::transmute_mut(self).zeroize();
+        }
     }
 }
 
@@ -294,15 +364,161 @@ mod test {
 
     #[test]
     fn test_sponge() {
-        use std::cast::transmute;
-        let mut sp = SpongeState::new(1152, 448);
+        use lanes::load_lanes;
+        use permutation::{Permutation, KeccakF1600};
+
+        let mut sp = Sponge::new(KeccakF1600::new(), 1152, 448).unwrap();
 
         let test_in = [0u8, ..144];
 
-        sp.absorb(test_in, 1152);
+        sp.absorb(test_in, 1152).unwrap();
 
-        unsafe {
-            assert_eq!(transmute::<&mut [u8], &[u64]>(sp.state), TEST_OUT);
+        let mut bytes = [0u8, ..200];
+        let mut lanes = [0u64, ..25];
+        sp.state.extract_bytes(bytes, 0);
+        load_lanes(bytes, lanes);
+        assert_eq!(lanes.as_slice(), TEST_OUT.as_slice());
+    }
+
+    #[test]
+    fn test_unaligned_absorb() {
+        use permutation::KeccakF1600;
+        use std::rand::{weak_rng, RngUtil};
+        use std::vec;
+
+        let mut rng = weak_rng();
+
+        for &total in [7u, 1088, 1095, 2500, 4001].iter() {
+            let msg = rng.gen_bytes((total + 7) / 8);
+
+            let mut once = Sponge::new(KeccakF1600::new(), 1088, 512).unwrap();
+            once.absorb(msg, total).unwrap();
+            let mut expected = [0u8, ..64];
+            once.squeeze(expected, 512).unwrap();
+
+            // Feed the same bits in random pieces, each repacked so that
+            // its first bit is bit 0 of its first byte.
+            let mut pieces = Sponge::new(KeccakF1600::new(), 1088, 512).unwrap();
+            let mut start = 0;
+            while start < total {
+                let mut len = rng.gen::<uint>() % 300 + 1;
+                if len > total - start {
+                    len = total - start;
+                }
+
+                let piece = vec::from_fn((len + 7) / 8, |k| {
+                    let mut b = 0u8;
+                    for j in range(0u, 8) {
+                        let bit = start + 8 * k + j;
+                        if 8 * k + j < len && msg[bit / 8] & (1 << (bit % 8)) != 0 {
+                            b |= 1 << j;
+                        }
+                    }
+                    b
+                });
+                assert!(pieces.absorb(piece, len).is_ok());
+                start += len;
+            }
+
+            let mut out = [0u8, ..64];
+            pieces.squeeze(out, 512).unwrap();
+            assert_eq!(out.as_slice(), expected.as_slice());
         }
     }
+
+    #[test]
+    fn test_squeeze_bits() {
+        use permutation::KeccakF1600;
+        use std::vec;
+
+        let mut once = Sponge::new(KeccakF1600::new(), 1344, 256).unwrap();
+        once.absorb([0xa3u8, ..40], 320).unwrap();
+        let mut stream = [0u8, ..400];
+        once.squeeze(stream, 3200).unwrap();
+
+        let bit = |i: uint| (stream[i / 8] >> (i % 8)) & 1;
+
+        // Odd lengths, some crossing the 1344-bit block boundary mid-byte.
+        let lengths = [13u, 1, 1000, 7, 333, 8, 16, 1300, 5, 514];
+        let mut sp = Sponge::new(KeccakF1600::new(), 1344, 256).unwrap();
+        sp.absorb([0xa3u8, ..40], 320).unwrap();
+
+        let mut start = 0;
+        for &len in lengths.iter() {
+            let mut out = vec::from_elem((len + 7) / 8, 0xFFu8);
+            assert!(sp.squeeze(out, len).is_ok());
+
+            for i in range(0, len) {
+                assert_eq!((out[i / 8] >> (i % 8)) & 1, bit(start + i));
+            }
+            if len % 8 != 0 {
+                assert_eq!(out[len / 8] >> (len % 8), 0);
+            }
+            start += len;
+        }
+
+        assert_eq!(sp.squeeze([0u8, ..1], 9), Err(BufferTooSmall));
+    }
+
+    #[test]
+    fn test_errors() {
+        use permutation::{KeccakF1600, KeccakP200};
+
+        assert_eq!(Sponge::new(KeccakF1600::new(), 1088, 256).unwrap_err(), InvalidParameters);
+        assert_eq!(Sponge::new(KeccakF1600::new(), 1084, 516).unwrap_err(), InvalidParameters);
+        assert_eq!(Sponge::new(KeccakP200::new(18), 0, 200).unwrap_err(), InvalidParameters);
+        assert_eq!(Sponge::new(KeccakF1600::new(), 1544, 56).unwrap_err(), RateTooLarge);
+
+        let mut sp = Sponge::new(KeccakF1600::new(), 1088, 512).unwrap();
+        assert_eq!(sp.absorb([0u8, ..2], 17), Err(BufferTooSmall));
+        assert!(sp.absorb([0u8, ..2], 16).is_ok());
+
+        let mut out = [0u8, ..4];
+        assert!(sp.squeeze(out, 3).is_ok());
+        assert!(sp.is_output_unaligned());
+        assert_eq!(sp.absorb([0u8, ..2], 16), Err(AbsorbAfterSqueeze));
+    }
+
+    #[test]
+    fn test_narrow_sponge() {
+        use permutation::{Permutation, KeccakP1600, KeccakP800};
+        use std::vec;
+
+        // The same sponge code drives other permutations; a rate that is not
+        // a multiple of the lane size still absorbs and squeezes consistently.
+        let msg = vec::from_fn(300, |i| i as u8);
+
+        let mut once = Sponge::new(KeccakP800::new(22), 552, 248).unwrap();
+        once.absorb(msg, msg.len() * 8).unwrap();
+        let mut a = [0u8, ..100];
+        once.squeeze(a, 800).unwrap();
+
+        let mut pieces = Sponge::new(KeccakP800::new(22), 552, 248).unwrap();
+        pieces.absorb(msg.slice_to(77), 77 * 8).unwrap();
+        pieces.absorb(msg.slice_from(77), (msg.len() - 77) * 8).unwrap();
+        let mut b = [0u8, ..100];
+        pieces.squeeze(b.mut_slice_to(30), 240).unwrap();
+        pieces.squeeze(b.mut_slice_from(30), 560).unwrap();
+
+        assert_eq!(a.as_slice(), b.as_slice());
+
+        let mut k12 = Sponge::new(KeccakP1600::new(12), 1344, 256).unwrap();
+        assert!(k12.absorb(msg, msg.len() * 8).is_ok());
+        let mut c = [0u8, ..100];
+        assert!(k12.squeeze(c, 800).is_ok());
+        assert!(c.as_slice() != a.as_slice());
+
+        // The same two blocks, padded and permuted by hand.
+        let mut perm = KeccakP1600::new(12);
+        perm.initialize();
+        perm.add_bytes(msg.slice_to(168), 0);
+        perm.permute();
+        perm.add_bytes(msg.slice_from(168), 0);
+        perm.add_bytes([0x01u8], 132);
+        perm.add_bytes([0x80u8], 167);
+        perm.permute();
+        let mut expected = [0u8, ..100];
+        perm.extract_bytes(expected, 0);
+        assert_eq!(c.as_slice(), expected.as_slice());
+    }
 }
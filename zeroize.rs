@@ -0,0 +1,43 @@
+// Wiping secret material. Every element is cleared with a volatile store,
+// which the compiler may not drop even when the memory is never read
+// again, as it would a plain assignment right before a free.
+//
+// `zeroize()` is always available. Building with `--cfg zeroize` also
+// wipes sponges, duplex objects, hashers and their cached digests when
+// they are dropped.
+
+use std::num::Zero;
+use std::unstable::intrinsics::volatile_store;
+
+/// Values that can hold secret material, like a keyed sponge.
+pub trait Zeroize {
+    /// Overwrite the secret state with zeroes. The value is left as if
+    /// freshly reset.
+    fn zeroize(&mut self);
+}
+
+/// Overwrite every element of `buf` with zero, e.g. a key or a tag the
+/// caller is done with.
+pub fn zeroize_slice<T: Zero>(buf: &mut [T]) {
+    for x in buf.mut_iter() {
+        unsafe {
+            volatile_store(x as *mut T, Zero::zero());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_zeroize_slice() {
+        let mut lanes = [0x0123456789ABCDEFu64, ..25];
+        zeroize_slice(lanes);
+        assert!(lanes.iter().all(|&x| x == 0));
+
+        let mut bytes = [0xAAu8, ..7];
+        zeroize_slice(bytes.mut_slice(2, 5));
+        assert_eq!(bytes.as_slice(), [0xAAu8, 0xAA, 0, 0, 0, 0xAA, 0xAA].as_slice());
+    }
+}